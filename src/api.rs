@@ -0,0 +1,241 @@
+use anyhow::Result;
+use futures::stream::{self, Stream};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::app::AppContext;
+use crate::batching::{BatchPolicy, Batcher};
+use crate::config::Config;
+use crate::conversation::Conversation;
+use crate::handler::{collect_answer, converse, unredact_answer};
+use crate::redact::Unredactor;
+use crate::requests::{generate_first_request, send_request, Chunk, Generation};
+
+/// A configured client, sharing the CLI's preprocessing, redaction and backend handling.
+///
+/// Cloning is cheap: clones share the HTTP connection pool and the redaction
+/// placeholders.
+#[derive(Clone)]
+pub struct Inlama {
+    ctx: Arc<AppContext>,
+}
+
+/// Builds an [`Inlama`], starting from the same defaults as the CLI.
+#[derive(Default)]
+pub struct InlamaBuilder {
+    config: Config,
+}
+
+impl InlamaBuilder {
+    /// Replaces every setting, e.g. with a config file parsed by the caller.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+    
+    /// The Ollama model to answer with, e.g. `llama3.2`.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.config.model = model.into();
+        self
+    }
+    
+    /// The system prompt sent with every request.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.config.prompt = prompt.into();
+        self
+    }
+    
+    /// Sends every request to the Ollama server at `url`, replacing the
+    /// configured server pool.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.config.url = vec![url.into()];
+        self.config.endpoints.clear();
+        self
+    }
+    
+    pub fn build(self) -> Result<Inlama> {
+        Ok(Inlama {
+            ctx: Arc::new(AppContext::new(self.config)?),
+        })
+    }
+}
+
+impl Inlama {
+    pub fn builder() -> InlamaBuilder {
+        InlamaBuilder::default()
+    }
+    
+    pub fn config(&self) -> &Config {
+        &self.ctx.config
+    }
+    
+    /// Answers `input` in one request, as oneshot mode does.
+    pub async fn complete(&self, input: &str) -> Result<String> {
+        let Some(body) = self.prepare(input) else {
+            return Ok(String::new());
+        };
+        let (answer, result) = collect_answer(&self.ctx, body, None, None).await;
+        result?;
+        Ok(unredact_answer(&self.ctx, answer))
+    }
+    
+    /// Answers `input` in one request, yielding the answer as it is generated.
+    ///
+    /// The request is only sent once the stream is first polled, on a task
+    /// spawned onto the Tokio runtime doing the polling; creating the stream
+    /// needs no runtime. A failed request ends the stream with an error.
+    /// Dropping the stream abandons the request.
+    pub fn stream(&self, input: &str) -> impl Stream<Item = Result<Chunk>> + Send + 'static {
+        let (tx, rx) = mpsc::channel::<Chunk>(100);
+        let state = ChunkStream {
+            ctx: Arc::clone(&self.ctx),
+            request: self.prepare(input).map(|body| (body, tx)),
+            rx,
+            sender_task: None,
+            unredactor: self.unredactor(),
+            thinking_unredactor: self.unredactor(),
+        };
+        stream::unfold(state, |mut state| async move {
+            let item = state.next().await?;
+            Some((item, state))
+        })
+    }
+    
+    /// Starts a buffered stream session, batching lines the way `--stream` does.
+    pub fn session(&self) -> Result<StreamSession> {
+        Ok(StreamSession {
+            ctx: Arc::clone(&self.ctx),
+            batcher: Batcher::new(BatchPolicy::from_config(&self.ctx.config)?),
+            conversation: Conversation::new(&self.ctx),
+        })
+    }
+    
+    fn unredactor(&self) -> Option<Unredactor> {
+        self.ctx
+            .preprocessor
+            .redactor()
+            .filter(|_| self.ctx.config.unredact && !self.ctx.config.dry_run)
+            .map(Unredactor::new)
+    }
+    
+    /// The redacted request body for `input`, or `None` if preprocessing leaves nothing.
+    fn prepare(&self, input: &str) -> Option<String> {
+        let lines = self.ctx.preprocessor.apply(input.lines().map(str::to_string).collect());
+        if lines.is_empty() {
+            return None;
+        }
+        Some(self.ctx.preprocessor.redact(&lines.join("\n")))
+    }
+}
+
+struct ChunkStream {
+    ctx: Arc<AppContext>,
+    /// The request body and the sender for its chunks, until the first poll sends it
+    request: Option<(String, mpsc::Sender<Chunk>)>,
+    rx: mpsc::Receiver<Chunk>,
+    sender_task: Option<JoinHandle<Result<Generation>>>,
+    unredactor: Option<Unredactor>,
+    thinking_unredactor: Option<Unredactor>,
+}
+
+impl ChunkStream {
+    async fn next(&mut self) -> Option<Result<Chunk>> {
+        if let Some((body, tx)) = self.request.take() {
+            let ctx = Arc::clone(&self.ctx);
+            self.sender_task = Some(tokio::spawn(async move {
+                let request = generate_first_request(&body, &ctx.config);
+                send_request(request, &ctx, tx).await
+            }));
+        }
+        loop {
+            match self.rx.recv().await {
+                Some(Chunk::Text(text)) => {
+                    let text = match &mut self.unredactor {
+                        Some(unredactor) => unredactor.push(&text),
+                        None => text,
+                    };
+                    // The unredactor may be holding back a partial placeholder
+                    if !text.is_empty() {
+                        return Some(Ok(Chunk::Text(text)));
+                    }
+                }
+                Some(Chunk::Thinking(text)) => {
+                    let text = match &mut self.thinking_unredactor {
+                        Some(unredactor) => unredactor.push(&text),
+                        None => text,
+                    };
+                    if !text.is_empty() {
+                        return Some(Ok(Chunk::Thinking(text)));
+                    }
+                }
+                None => {
+                    // Reasoning comes before the answer, so its held-back tail goes first
+                    let thinking = self.thinking_unredactor.take().map(|mut u| u.finish()).unwrap_or_default();
+                    if !thinking.is_empty() {
+                        return Some(Ok(Chunk::Thinking(thinking)));
+                    }
+                    let sender_task = self.sender_task.take()?;
+                    let rest = self.unredactor.take().map(|mut u| u.finish()).unwrap_or_default();
+                    return match sender_task.await {
+                        Ok(Ok(_)) if rest.is_empty() => None,
+                        Ok(Ok(_)) => Some(Ok(Chunk::Text(rest))),
+                        Ok(Err(e)) => Some(Err(e)),
+                        Err(e) => Some(Err(anyhow::anyhow!("Task error: {}", e))),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Batches lines into requests that continue one conversation, like `--stream`.
+///
+/// Size and `flush_on` triggers fire from [`StreamSession::push`]; time-based
+/// flushes are up to the caller, by calling [`StreamSession::flush`] once
+/// [`StreamSession::deadline`] has passed.
+pub struct StreamSession {
+    ctx: Arc<AppContext>,
+    batcher: Batcher,
+    conversation: Conversation,
+}
+
+impl StreamSession {
+    /// Buffers a line, returning the answer if it completed a batch.
+    pub async fn push(&mut self, line: impl Into<String>) -> Result<Option<String>> {
+        if self.batcher.push(line.into()) {
+            self.flush().await
+        } else {
+            Ok(None)
+        }
+    }
+    
+    /// When the buffered lines are due to be sent, if there are any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.batcher.deadline()
+    }
+    
+    /// Sends the buffered lines now, returning the answer.
+    ///
+    /// Returns `None` when nothing was buffered or preprocessing dropped every line.
+    pub async fn flush(&mut self) -> Result<Option<String>> {
+        if self.batcher.is_empty() {
+            return Ok(None);
+        }
+        let lines = self.ctx.preprocessor.apply(self.batcher.take());
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        let body = self.ctx.preprocessor.redact(&lines.join("\n"));
+        
+        let Some((answer, generation)) = converse(&self.ctx, &self.conversation, &body, |_| Ok(())).await? else {
+            return Ok(None);
+        };
+        self.conversation.record(&body, &answer, generation.context);
+        if self.conversation.needs_summary() {
+            self.conversation.summarize(&self.ctx).await?;
+        }
+        Ok(Some(unredact_answer(&self.ctx, answer)))
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Certificate, Client, Identity, Proxy, Url};
+use std::path::Path;
+use std::time::Duration;
+use std::{env, fs};
+
+use crate::cache::ResponseCache;
+use crate::config::Config;
+use crate::endpoints::EndpointPool;
+use crate::preprocess::Preprocessor;
+use crate::signals::Cancellation;
+
+/// Shared state handed to the handlers for the lifetime of a run.
+///
+/// Holding a single `Client` lets every request reuse pooled connections,
+/// which matters in stream mode where each buffer flush is a new request.
+pub struct AppContext {
+    pub config: Config,
+    pub client: Client,
+    pub endpoints: EndpointPool,
+    pub preprocessor: Preprocessor,
+    pub cancellation: Cancellation,
+    /// Shared so every request in the run goes through the same index lock
+    pub cache: Option<ResponseCache>,
+}
+
+impl AppContext {
+    pub fn new(config: Config) -> Result<Self> {
+        let client = build_client(&config)?;
+        let endpoints = EndpointPool::from_config(&config);
+        if endpoints.urls().is_empty() {
+            return Err(anyhow::anyhow!("No server URL configured"));
+        }
+        let preprocessor = Preprocessor::new(&config)?;
+        let cache = ResponseCache::from_config(&config);
+        Ok(Self { config, client, endpoints, preprocessor, cancellation: Cancellation::new(), cache })
+    }
+}
+
+fn build_client(config: &Config) -> Result<Client> {
+    let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let headers = default_headers(config)?;
+    
+    if config.debug {
+        // Sensitive values are masked so tokens never end up in debug logs
+        for (name, value) in &headers {
+            let shown = if value.is_sensitive() {
+                "<redacted>"
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            eprintln!("Default header: {}: {}", name, shown);
+        }
+    }
+    
+    // HTTP/2 is negotiated through ALPN when the server supports it
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .default_headers(headers)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(4)
+        .tcp_keepalive(Duration::from_secs(60))
+        .http2_adaptive_window(true);
+    
+    if let Some(proxy) = &config.proxy {
+        if config.debug {
+            eprintln!("Using proxy: {}", without_credentials(proxy));
+        }
+        let proxy = Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid proxy URL {}: {}", without_credentials(proxy), e))?;
+        builder = builder.proxy(proxy);
+    }
+    
+    if let Some(ca_cert) = &config.ca_cert {
+        let pem = read_file(ca_cert)?;
+        let cert = Certificate::from_pem(&pem)
+            .map_err(|e| anyhow::anyhow!("Invalid CA certificate {}: {}", ca_cert, e))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_file(cert_path)?;
+            let key = read_file(key_path)?;
+            let identity = Identity::from_pkcs8_pem(&cert, &key)
+                .map_err(|e| anyhow::anyhow!("Invalid client certificate {}: {}", cert_path, e))?;
+            builder = builder.identity(identity);
+        }
+        (Some(_), None) | (None, Some(_)) => {
+            return Err(anyhow::anyhow!("client_cert and client_key must be set together"));
+        }
+        (None, None) => {}
+    }
+    
+    if config.insecure {
+        eprintln!("Warning: TLS certificate verification is disabled");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    
+    let client = builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("Error building HTTP client: {}", e))?;
+    
+    if config.debug {
+        eprintln!("HTTP client ready");
+    }
+    
+    Ok(client)
+}
+
+fn default_headers(config: &Config) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    
+    for header in &config.headers {
+        let (name, value) = header.split_once(':').ok_or_else(|| {
+            anyhow::anyhow!("Invalid header {:?}, expected NAME:VALUE", header)
+        })?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid header name {:?}: {}", name, e))?;
+        let mut value = HeaderValue::from_str(value.trim())
+            .map_err(|e| anyhow::anyhow!("Invalid value for header {}: {}", name, e))?;
+        if is_secret_header(&name) {
+            value.set_sensitive(true);
+        }
+        headers.append(name, value);
+    }
+    
+    if let Some(token) = load_token(config)? {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| anyhow::anyhow!("Bearer token contains invalid characters"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    
+    Ok(headers)
+}
+
+/// Headers whose values are masked in debug output: credentials, API keys, tokens and cookies.
+pub fn is_secret_header(name: &HeaderName) -> bool {
+    // Names are lowercase; "auth" covers Authorization and Proxy-Authorization
+    ["auth", "token", "key", "secret", "password", "cookie"]
+        .iter()
+        .any(|word| name.as_str().contains(word))
+}
+
+/// `url` with any `user:pass@` replaced, for showing in logs and errors.
+pub fn without_credentials(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if !parsed.username().is_empty() || parsed.password().is_some() => {
+            let _ = parsed.set_username("***");
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        Ok(_) => url.to_string(),
+        // Unparseable, so only strip what looks like userinfo
+        Err(_) => match url.rsplit_once('@') {
+            Some((_, host)) => format!("***@{}", host),
+            None => url.to_string(),
+        },
+    }
+}
+
+fn load_token(config: &Config) -> Result<Option<String>> {
+    if let Some(var) = &config.token_env {
+        let token = env::var(var)
+            .map_err(|_| anyhow::anyhow!("Environment variable {} is not set", var))?;
+        return Ok(Some(token.trim().to_string()));
+    }
+    
+    if let Some(path) = &config.token_file {
+        let token = String::from_utf8(read_file(path)?)
+            .map_err(|_| anyhow::anyhow!("Token file {} is not valid UTF-8", path))?;
+        return Ok(Some(token.trim().to_string()));
+    }
+    
+    Ok(None)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    let expanded = shellexpand::tilde(path);
+    fs::read(Path::new(expanded.as_ref()))
+        .map_err(|e| anyhow::anyhow!("Error reading {}: {}", path, e))
+}
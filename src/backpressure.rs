@@ -0,0 +1,177 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+use crate::config::Config;
+
+/// What happens to new input lines while the model is busy and the queue is full.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Stop reading until there is room, stalling the upstream producer
+    Block,
+    /// Discard the oldest queued line to make room
+    DropOldest,
+    /// Discard the incoming line
+    DropNewest,
+    /// Keep one in every `sample_rate` overflowing lines, discarding the oldest for it
+    Sample,
+    /// Count overflowing lines and queue a single summary line in their place
+    Coalesce,
+}
+
+/// Lines lost to the overflow policy, reported with `--stats`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DropCounters {
+    pub received: u64,
+    pub dropped: u64,
+    pub coalesced: u64,
+}
+
+impl DropCounters {
+    pub fn is_empty(&self) -> bool {
+        self.dropped == 0 && self.coalesced == 0
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    lines: VecDeque<String>,
+    closed: bool,
+    overflowed: u64,
+    skipped: u64,
+    counters: DropCounters,
+}
+
+/// Bounded queue between the stdin reader and the stream handler.
+///
+/// Unlike a plain channel it applies an [`OverflowPolicy`] when the handler
+/// falls behind, so a slow model doesn't have to stall the process writing
+/// into the pipe.
+pub struct InputQueue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    sample_rate: u64,
+    readable: Notify,
+    writable: Notify,
+}
+
+impl InputQueue {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            capacity: config.queue_capacity.max(1),
+            policy: config.overflow,
+            sample_rate: config.sample_rate.max(1),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        }
+    }
+    
+    pub async fn push(&self, line: String) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.lines.len() < self.capacity {
+                    state.counters.received += 1;
+                    if state.skipped > 0 {
+                        let summary = skipped_summary(state.skipped);
+                        state.lines.push_back(summary);
+                        state.skipped = 0;
+                    }
+                    state.lines.push_back(line);
+                    drop(state);
+                    self.readable.notify_one();
+                    return;
+                }
+                
+                match self.policy {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropOldest => {
+                        state.counters.received += 1;
+                        state.counters.dropped += 1;
+                        state.lines.pop_front();
+                        state.lines.push_back(line);
+                        return;
+                    }
+                    OverflowPolicy::DropNewest => {
+                        state.counters.received += 1;
+                        state.counters.dropped += 1;
+                        return;
+                    }
+                    OverflowPolicy::Sample => {
+                        state.counters.received += 1;
+                        state.counters.dropped += 1;
+                        state.overflowed += 1;
+                        if state.overflowed.is_multiple_of(self.sample_rate) {
+                            state.lines.pop_front();
+                            state.lines.push_back(line);
+                        }
+                        return;
+                    }
+                    OverflowPolicy::Coalesce => {
+                        state.counters.received += 1;
+                        state.counters.coalesced += 1;
+                        state.skipped += 1;
+                        return;
+                    }
+                }
+            }
+            
+            // Block: wait until the handler takes a line
+            self.writable.notified().await;
+        }
+    }
+    
+    /// Marks the end of input; `recv` returns `None` once the queue drains.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.readable.notify_one();
+    }
+    
+    /// Takes the next line. Cancel safe, so it can be used in `select!`.
+    pub async fn recv(&self) -> Option<String> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(line) = state.lines.pop_front() {
+                    drop(state);
+                    self.writable.notify_one();
+                    return Some(line);
+                }
+                if state.skipped > 0 {
+                    let summary = skipped_summary(state.skipped);
+                    state.skipped = 0;
+                    return Some(summary);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.readable.notified().await;
+        }
+    }
+    
+    pub fn counters(&self) -> DropCounters {
+        self.state.lock().unwrap().counters.clone()
+    }
+}
+
+fn skipped_summary(count: u64) -> String {
+    format!("... {} similar lines skipped", group_thousands(count))
+}
+
+fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
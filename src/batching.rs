@@ -0,0 +1,122 @@
+use anyhow::Result;
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::tokens::estimate_tokens;
+
+/// When a buffered stream batch is sent to the model.
+///
+/// The idle gap (`buffer_time`) is always active; every other trigger is optional
+/// and whichever fires first wins.
+#[derive(Debug, Clone)]
+pub struct BatchPolicy {
+    pub idle: Duration,
+    pub max_latency: Option<Duration>,
+    pub max_lines: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub max_tokens: Option<usize>,
+    pub flush_on: Vec<Regex>,
+}
+
+impl BatchPolicy {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let flush_on = config.flush_on
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|e| anyhow::anyhow!("Invalid flush_on pattern {:?}: {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let max_latency = config.batch_max_latency
+            .map(|seconds| {
+                Duration::try_from_secs_f64(seconds).map_err(|_| {
+                    anyhow::anyhow!("Invalid batch_max_latency {}: expected a non-negative number of seconds", seconds)
+                })
+            })
+            .transpose()?;
+        
+        Ok(Self {
+            idle: Duration::from_secs(config.buffer_time),
+            max_latency,
+            max_lines: config.batch_max_lines,
+            max_bytes: config.batch_max_bytes,
+            max_tokens: config.batch_max_tokens,
+            flush_on,
+        })
+    }
+    
+    /// How long a partly assembled input record waits for more lines before it is sent as is.
+    pub fn record_idle(&self) -> Duration {
+        let idle = self.max_latency.map_or(self.idle, |max| max.min(self.idle));
+        // Not so short that a record written in several pieces is split up
+        idle.max(Duration::from_millis(100))
+    }
+}
+
+/// Accumulates stream lines until the batch policy says to flush.
+pub struct Batcher {
+    policy: BatchPolicy,
+    lines: Vec<String>,
+    bytes: usize,
+    tokens: usize,
+    first_at: Option<Instant>,
+    last_at: Option<Instant>,
+}
+
+impl Batcher {
+    pub fn new(policy: BatchPolicy) -> Self {
+        Self {
+            policy,
+            lines: Vec::new(),
+            bytes: 0,
+            tokens: 0,
+            first_at: None,
+            last_at: None,
+        }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+    
+    /// Buffers a line and returns true if a size or pattern trigger fired.
+    pub fn push(&mut self, line: String) -> bool {
+        let now = Instant::now();
+        self.first_at.get_or_insert(now);
+        self.last_at = Some(now);
+        
+        let matched = self.policy.flush_on.iter().any(|re| re.is_match(&line));
+        self.bytes += line.len() + 1;
+        self.tokens += estimate_tokens(&line);
+        self.lines.push(line);
+        
+        matched
+            || self.policy.max_lines.is_some_and(|max| self.lines.len() >= max)
+            || self.policy.max_bytes.is_some_and(|max| self.bytes >= max)
+            || self.policy.max_tokens.is_some_and(|max| self.tokens >= max)
+    }
+    
+    /// The instant the buffered batch is due: after the idle gap since the last
+    /// line, or at the hard deadline since the first one, whichever is earlier.
+    pub fn deadline(&self) -> Option<Instant> {
+        let idle = self.last_at.map(|last| last + self.policy.idle);
+        let latency = self.first_at.zip(self.policy.max_latency).map(|(first, max)| first + max);
+        match (idle, latency) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+    
+    pub fn take(&mut self) -> Vec<String> {
+        self.bytes = 0;
+        self.tokens = 0;
+        self.first_at = None;
+        self.last_at = None;
+        std::mem::take(&mut self.lines)
+    }
+}
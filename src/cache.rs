@@ -0,0 +1,301 @@
+use anyhow::Result;
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cassette::write_atomically;
+use crate::config::Config;
+
+const INDEX_FILE: &str = "index.json";
+const LOCK_FILE: &str = "index.lock";
+
+/// `inlama cache ...`
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommand {
+    /// Show where the cache is and how much it holds
+    Stats,
+    /// Delete every cached answer
+    Clear,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Unix seconds when the answer was stored
+    created: u64,
+    /// The index's use counter when the answer was last stored or served, for LRU eviction
+    #[serde(default)]
+    last_use: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    /// Bumped on every store and hit; unlike a timestamp it never ties
+    #[serde(default)]
+    uses: u64,
+}
+
+impl Index {
+    fn next_use(&mut self) -> u64 {
+        self.uses += 1;
+        self.uses
+    }
+}
+
+/// Answers stored on disk by request key, replayed instead of asking the server again.
+///
+/// Like recordings, each answer is kept as the raw NDJSON the server sent, so a
+/// hit is streamed through the same parsing and output as a live response.
+/// `index.json` tracks when entries were stored and last used; every
+/// read-modify-write of it happens under [`ResponseCache::lock`].
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    max_bytes: Option<u64>,
+    refresh: bool,
+    tasks: Mutex<()>,
+}
+
+/// Exclusive access to the index, for this process and any other run sharing the directory.
+struct IndexLock<'a> {
+    _guard: MutexGuard<'a, ()>,
+    _file: File,
+}
+
+impl ResponseCache {
+    /// The configured cache, or `None` when caching is off.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.cache {
+            return None;
+        }
+        Some(Self::open(config))
+    }
+    
+    /// The cache directory as configured, whether or not caching is on.
+    pub fn open(config: &Config) -> Self {
+        let dir = match &config.cache_dir {
+            Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            None => dirs::cache_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join("inlama"),
+        };
+        Self {
+            dir,
+            ttl: config.cache_ttl.filter(|ttl| *ttl > 0).map(Duration::from_secs),
+            max_bytes: config.cache_max_bytes,
+            refresh: config.refresh,
+            tasks: Mutex::new(()),
+        }
+    }
+    
+    /// The stored answer for `key`, unless it has expired or `--refresh` is set.
+    pub fn lookup(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        if self.refresh {
+            return Ok(None);
+        }
+        let _lock = self.lock()?;
+        let mut index = self.load_index();
+        let next_use = index.next_use();
+        let Some(entry) = index.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        
+        let now = now();
+        if self.is_expired(entry, now) {
+            index.entries.remove(key);
+            let _ = fs::remove_file(self.path(key));
+            self.save_index(&index)?;
+            return Ok(None);
+        }
+        
+        match fs::read(self.path(key)) {
+            Ok(bytes) => {
+                entry.last_use = next_use;
+                self.save_index(&index)?;
+                Ok(Some(bytes))
+            }
+            Err(_) => {
+                // The file was removed behind our back; forget the entry
+                index.entries.remove(key);
+                self.save_index(&index)?;
+                Ok(None)
+            }
+        }
+    }
+    
+    pub fn store(&self, key: &str, response: &[u8]) -> Result<()> {
+        let _lock = self.lock()?;
+        write_atomically(&self.path(key), response)
+            .map_err(|e| anyhow::anyhow!("Error writing cache entry: {}", e))?;
+        
+        let now = now();
+        let mut index = self.load_index();
+        let last_use = index.next_use();
+        index.entries.insert(key.to_string(), Entry {
+            created: now,
+            last_use,
+            bytes: response.len() as u64,
+        });
+        
+        // Drop expired entries first, then the least recently used ones
+        let expired: Vec<String> = index.entries
+            .iter()
+            .filter(|(_, entry)| self.is_expired(entry, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.evict(&mut index, &key);
+        }
+        // Answers the index lost track of would otherwise never count against the limit
+        for orphan in self.entry_files().into_iter().filter(|key| !index.entries.contains_key(key)) {
+            let _ = fs::remove_file(self.path(&orphan));
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            // The answer just stored is kept even if it alone is over the limit
+            let mut by_use: Vec<(String, u64)> = index.entries
+                .iter()
+                .filter(|(stored, _)| stored.as_str() != key)
+                .map(|(stored, entry)| (stored.clone(), entry.last_use))
+                .collect();
+            by_use.sort_by_key(|(_, last_use)| *last_use);
+            for (oldest, _) in by_use {
+                if total_bytes(&index) <= max_bytes {
+                    break;
+                }
+                self.evict(&mut index, &oldest);
+            }
+        }
+        
+        self.save_index(&index)
+    }
+    
+    /// Handles `inlama cache stats|clear`.
+    pub fn run(&self, command: &CacheCommand) -> Result<()> {
+        match command {
+            CacheCommand::Stats => {
+                let index = self.load_index();
+                let now = now();
+                let expired = index.entries.values().filter(|e| self.is_expired(e, now)).count();
+                println!("Directory: {}", self.dir.display());
+                println!("Entries: {} ({} expired)", index.entries.len(), expired);
+                match self.max_bytes {
+                    Some(max_bytes) => println!("Size: {} of {} bytes", total_bytes(&index), max_bytes),
+                    None => println!("Size: {} bytes", total_bytes(&index)),
+                }
+                match self.ttl {
+                    Some(ttl) => println!("TTL: {} seconds", ttl.as_secs()),
+                    None => println!("TTL: none"),
+                }
+            }
+            CacheCommand::Clear => {
+                let removed = self.clear()?;
+                println!("Removed {} cached answers from {}", removed, self.dir.display());
+            }
+        }
+        Ok(())
+    }
+    
+    /// Deletes every stored answer, including any the index lost track of.
+    ///
+    /// Other files are left alone, in case `cache_dir` points somewhere shared.
+    pub fn clear(&self) -> Result<usize> {
+        let _lock = self.lock()?;
+        let removed = self
+            .entry_files()
+            .into_iter()
+            .filter(|key| fs::remove_file(self.path(key)).is_ok())
+            .count();
+        self.save_index(&Index::default())?;
+        Ok(removed)
+    }
+    
+    pub fn dir(&self) -> String {
+        self.dir.display().to_string()
+    }
+    
+    /// The keys of every answer file in the directory, whether or not the index lists it.
+    ///
+    /// Only files named like a request key count, so nothing else that happens
+    /// to live in the directory is ever touched.
+    fn entry_files(&self) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let stem = path.file_stem()?.to_str()?;
+                (path.extension().is_some_and(|ext| ext == "ndjson") && is_key(stem)).then(|| stem.to_string())
+            })
+            .collect()
+    }
+    
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.ndjson", key))
+    }
+    
+    fn is_expired(&self, entry: &Entry, now: u64) -> bool {
+        self.ttl.is_some_and(|ttl| now.saturating_sub(entry.created) >= ttl.as_secs())
+    }
+    
+    fn evict(&self, index: &mut Index, key: &str) {
+        index.entries.remove(key);
+        let _ = fs::remove_file(self.path(key));
+    }
+    
+    /// A missing or unreadable index is treated as an empty cache.
+    fn load_index(&self) -> Index {
+        fs::read(self.dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+    
+    /// Written to a temporary file and renamed, so a concurrent run never reads half an index.
+    fn save_index(&self, index: &Index) -> Result<()> {
+        write_atomically(&self.dir.join(INDEX_FILE), &serde_json::to_vec(index)?)
+            .map_err(|e| anyhow::anyhow!("Error writing cache index: {}", e))
+    }
+    
+    /// Serialises index updates between tasks and between runs sharing the directory.
+    ///
+    /// Not every platform's file locks keep out other threads of the same
+    /// process, so tasks in this run also queue on a mutex first.
+    fn lock(&self) -> Result<IndexLock<'_>> {
+        let guard = self.tasks.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow::anyhow!("Error creating cache directory {}: {}", self.dir.display(), e))?;
+        let file = lock_file(&self.dir.join(LOCK_FILE))
+            .map_err(|e| anyhow::anyhow!("Error locking cache index: {}", e))?;
+        Ok(IndexLock { _guard: guard, _file: file })
+    }
+}
+
+/// Opens `path` and takes an exclusive lock on it, released when the file is closed.
+fn lock_file(path: &Path) -> std::io::Result<File> {
+    let file = File::options().create(true).truncate(false).write(true).open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Whether `name` looks like a [`request_key`](crate::requests::request_key): 64 hex digits.
+fn is_key(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn total_bytes(index: &Index) -> u64 {
+    index.entries.values().map(|entry| entry.bytes).sum()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,88 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::requests::OllamaRequest;
+
+/// What `--replay` does with a request that has no recording.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayMiss {
+    /// Fail the request
+    Error,
+    /// Send it to the server as usual
+    Live,
+}
+
+/// A directory of recorded requests and their raw streamed responses.
+///
+/// Each exchange is stored under its [`request_key`](crate::requests::request_key)
+/// as `<key>.json` (the request body) and `<key>.ndjson` (the response bytes
+/// exactly as the server sent them), so a replay goes through the same parsing
+/// as a live answer.
+pub struct Cassette {
+    dir: PathBuf,
+}
+
+impl Cassette {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(shellexpand::tilde(dir).as_ref()),
+        }
+    }
+    
+    /// The recorded response for `key`, if there is one.
+    pub fn load(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.dir.join(format!("{}.ndjson", key));
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("Error reading recording {}: {}", path.display(), e)),
+        }
+    }
+    
+    pub fn store(&self, key: &str, request: &OllamaRequest, response: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow::anyhow!("Error creating {}: {}", self.dir.display(), e))?;
+        
+        let request_path = self.dir.join(format!("{}.json", key));
+        let body = serde_json::to_string_pretty(request)? + "\n";
+        write_atomically(&request_path, body.as_bytes())
+            .map_err(|e| anyhow::anyhow!("Error writing {}: {}", request_path.display(), e))?;
+        
+        // Written last, so a recording only counts once it is complete
+        let response_path = self.dir.join(format!("{}.ndjson", key));
+        write_atomically(&response_path, response)
+            .map_err(|e| anyhow::anyhow!("Error writing {}: {}", response_path.display(), e))
+    }
+    
+    pub fn dir(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place,
+/// so a reader never sees a half-written file.
+///
+/// The temporary name is unique to this process and write, so concurrent writers
+/// never share one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let temp = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    
+    let result = fs::write(&temp, contents).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
@@ -1,6 +1,14 @@
 use clap::{Parser, Subcommand};
 use clap_complete::{Shell, generate, Generator};
 
+use crate::backpressure::OverflowPolicy;
+use crate::cache::CacheCommand;
+use crate::cassette::ReplayMiss;
+use crate::decode::{BinaryInput, InputEncoding};
+use crate::endpoints::BalanceStrategy;
+use crate::limits::TruncateStrategy;
+use crate::output::OutputFormat;
+
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
@@ -16,14 +24,266 @@ pub struct Cli {
     #[arg(short = 'b', long, default_value = "1")]
     pub buffer_time: u64,
     
-    /// URL for model
-    #[arg(short, long, default_value = "http://localhost:11434")]
-    pub url: String,
+    /// Flush a stream batch once it holds this many lines
+    #[arg(long, value_name = "N")]
+    pub batch_max_lines: Option<usize>,
+    
+    /// Flush a stream batch once it holds this many bytes
+    #[arg(long, value_name = "N")]
+    pub batch_max_bytes: Option<usize>,
+    
+    /// Flush a stream batch once it holds about this many tokens
+    #[arg(long, value_name = "N")]
+    pub batch_max_tokens: Option<usize>,
+    
+    /// Flush a stream batch at most this many seconds after its first line, even if input keeps coming
+    #[arg(long, alias = "max-latency", value_name = "SECONDS")]
+    pub batch_max_latency: Option<f64>,
+    
+    /// Flush a stream batch immediately when a line matches REGEX (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    pub flush_on: Vec<String>,
+    
+    /// What to do with new stream input while the model is busy and the queue is full
+    #[arg(long, value_enum)]
+    pub overflow: Option<OverflowPolicy>,
+    
+    /// With --overflow sample, keep one in every N overflowing lines
+    #[arg(long, value_name = "N")]
+    pub sample_rate: Option<u64>,
+    
+    /// Number of stream input lines buffered while a request is in flight
+    #[arg(long, value_name = "N")]
+    pub queue_capacity: Option<usize>,
+    
+    /// Summarise the stream conversation once its context passes this many tokens (0 disables)
+    #[arg(long, value_name = "N")]
+    pub context_max_tokens: Option<usize>,
+    
+    /// Number of recent exchanges kept verbatim after a summary
+    #[arg(long, value_name = "N")]
+    pub keep_recent: Option<usize>,
+    
+    /// Character encoding of the input
+    #[arg(long, value_enum, value_name = "ENCODING")]
+    pub input_encoding: Option<InputEncoding>,
+    
+    /// What to do when the input looks like binary data
+    #[arg(long, value_enum)]
+    pub binary: Option<BinaryInput>,
+    
+    /// Keep ANSI colour and cursor escape sequences in the input instead of stripping them
+    #[arg(long)]
+    pub keep_ansi: bool,
+    
+    /// Longest input line kept in full; longer lines are cut to this many bytes (default 1 MiB)
+    #[arg(long, value_name = "N")]
+    pub max_line_bytes: Option<usize>,
+    
+    /// Most input read in oneshot mode, in bytes
+    #[arg(long, value_name = "N")]
+    pub max_input_bytes: Option<usize>,
+    
+    /// Most input read in oneshot mode, in estimated tokens
+    #[arg(long, value_name = "N")]
+    pub max_input_tokens: Option<usize>,
+    
+    /// What to keep when the input is over --max-input-bytes or --max-input-tokens
+    #[arg(long, value_enum)]
+    pub truncate: Option<TruncateStrategy>,
+    
+    /// Start a new input record at every line matching REGEX, keeping continuation lines (e.g. stack traces) with it
+    #[arg(long, value_name = "REGEX")]
+    pub record_start: Option<String>,
+    
+    /// Split input into records on STRING instead of newlines (\n, \t and \0 escapes allowed)
+    #[arg(long, value_name = "STRING")]
+    pub record_sep: Option<String>,
+    
+    /// Input records are separated by NUL bytes (as from find -print0)
+    #[arg(short = '0', long = "null")]
+    pub null_separated: bool,
+    
+    /// Each input record is a JSON value, which may span several lines
+    #[arg(long)]
+    pub json_records: bool,
+    
+    /// Input is JSON Lines; send the fields chosen with --field (or the whole object) to the model
+    #[arg(long)]
+    pub jsonl_in: bool,
+    
+    /// Field of each JSON record to send, as a dotted path or JSON pointer (repeatable)
+    #[arg(long = "field", value_name = "PATH")]
+    pub fields: Vec<String>,
+    
+    /// Call the model once per JSON record and print the record with the answer stored in field NAME
+    #[arg(long, value_name = "NAME")]
+    pub merge_into: Option<String>,
+    
+    /// Input is CSV with a header row; call the model once per row and print the table with answer columns added
+    #[arg(long)]
+    pub csv: bool,
+    
+    /// Like --csv, for tab-separated input
+    #[arg(long, conflicts_with = "csv")]
+    pub tsv: bool,
+    
+    /// Text sent for each table row, with {{row.COLUMN}} replaced by the row's cells
+    #[arg(long, value_name = "TEMPLATE")]
+    pub row_template: Option<String>,
+    
+    /// Column added to the table, optionally answered with its own prompt (NAME or NAME=PROMPT, repeatable)
+    #[arg(long = "column", value_name = "NAME[=PROMPT]")]
+    pub columns: Vec<String>,
+    
+    /// Number of table rows sent to the model at the same time
+    #[arg(long, value_name = "N")]
+    pub concurrency: Option<usize>,
+    
+    /// Collapse repetitive lines into "template ×count" summaries before sending
+    #[arg(long)]
+    pub collapse: bool,
+    
+    /// Minimum number of matching lines before a template is collapsed
+    #[arg(long, value_name = "N")]
+    pub collapse_min_count: Option<usize>,
+    
+    /// Replace secrets and personal data (keys, tokens, emails, IPs, card numbers) with placeholders before sending
+    #[arg(long)]
+    pub redact: bool,
+    
+    /// Extra redaction rule, replacing matches of REGEX with <NAME_n> (repeatable, implies --redact)
+    #[arg(long = "redact-rule", value_name = "NAME=REGEX")]
+    pub redact_rules: Vec<String>,
+    
+    /// Put the original values back in place of redaction placeholders in the answer
+    #[arg(long)]
+    pub unredact: bool,
+    
+    /// URL for model (repeat for multiple servers)
+    #[arg(short, long)]
+    pub url: Vec<String>,
+    
+    /// How to spread requests across multiple servers
+    #[arg(long, value_enum)]
+    pub balance: Option<BalanceStrategy>,
     
     /// Model to use
     #[arg(short, long, default_value = "llama3.2")]
     pub model: String,
     
+    /// How long the model stays loaded after a request (e.g. 30m, 1h, -1 for forever)
+    #[arg(long)]
+    pub keep_alive: Option<String>,
+    
+    /// Environment variable holding a bearer token for the server
+    #[arg(long, value_name = "VAR")]
+    pub token_env: Option<String>,
+    
+    /// File containing a bearer token for the server
+    #[arg(long, value_name = "PATH")]
+    pub token_file: Option<String>,
+    
+    /// Extra header sent with every request (NAME:VALUE, repeatable)
+    #[arg(short = 'H', long = "header", value_name = "NAME:VALUE")]
+    pub headers: Vec<String>,
+    
+    /// HTTP(S) proxy to route requests through
+    #[arg(long)]
+    pub proxy: Option<String>,
+    
+    /// PEM bundle of additional trusted CA certificates
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<String>,
+    
+    /// PEM client certificate for mutual TLS
+    #[arg(long, value_name = "PATH")]
+    pub client_cert: Option<String>,
+    
+    /// PEM (PKCS#8) private key for the client certificate
+    #[arg(long, value_name = "PATH")]
+    pub client_key: Option<String>,
+    
+    /// Skip TLS certificate verification
+    #[arg(long)]
+    pub insecure: bool,
+    
+    /// Output format
+    #[arg(short, long, value_enum)]
+    pub output: Option<OutputFormat>,
+    
+    /// Print the answer as-is instead of rendering markdown on a terminal
+    #[arg(long)]
+    pub raw: bool,
+    
+    /// Only print the first fenced code block of the answer, optionally of language LANG
+    #[arg(long, value_name = "LANG", num_args = 0..=1, default_missing_value = "")]
+    pub extract_code: Option<String>,
+    
+    /// With --extract-code, print every matching block instead of the first
+    #[arg(long)]
+    pub all_blocks: bool,
+    
+    /// Save code blocks from the answer as files in DIR
+    #[arg(long, value_name = "DIR")]
+    pub write_blocks: Option<String>,
+    
+    /// Also write the output to PATH while streaming to stdout
+    #[arg(long, visible_alias = "output-file", value_name = "PATH")]
+    pub tee: Option<String>,
+    
+    /// Save every request and its raw response in DIR, for replaying later
+    #[arg(long, value_name = "DIR")]
+    pub record: Option<String>,
+    
+    /// Answer from recordings in DIR instead of the server
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<String>,
+    
+    /// What --replay does with a request that was never recorded
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub replay_miss: Option<ReplayMiss>,
+    
+    /// Always ask the server, without reading or storing cached answers
+    #[arg(long)]
+    pub no_cache: bool,
+    
+    /// Ask the server even if the answer is cached, and cache the new answer
+    #[arg(long, conflicts_with = "no_cache")]
+    pub refresh: bool,
+    
+    /// Keep cached answers in DIR instead of ~/.cache/inlama
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<String>,
+    
+    /// Discard cached answers older than this many seconds (0 keeps them forever)
+    #[arg(long, value_name = "SECONDS")]
+    pub cache_ttl: Option<u64>,
+    
+    /// Evict the least recently used answers once the cache is larger than N bytes
+    #[arg(long, value_name = "N")]
+    pub cache_max_bytes: Option<u64>,
+    
+    /// Print each request that would be sent, with estimated token counts, without contacting a server
+    #[arg(long)]
+    pub dry_run: bool,
+    
+    /// Print generation statistics to stderr after each response
+    #[arg(long)]
+    pub stats: bool,
+    
+    /// Append generation statistics as JSON lines to FILE
+    #[arg(long, value_name = "FILE")]
+    pub stats_json: Option<String>,
+    
+    /// Ask the model to think before answering (Ollama `think` flag); --think=false disables it
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub think: Option<bool>,
+    
+    /// Print the model's reasoning to stderr instead of hiding it
+    #[arg(long)]
+    pub show_thinking: bool,
+    
     /// Enable debug output
     #[arg(short, long)]
     pub debug: bool,
@@ -40,6 +300,11 @@ pub enum Commands {
         /// Shell type
         shell: Shell,
     },
+    /// Inspect or clear the response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
 }
 
 pub fn generate_completions(shell: Shell) {
@@ -0,0 +1,223 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// A fenced code block found in a model answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    pub language: Option<String>,
+    pub filename: Option<String>,
+    pub content: String,
+}
+
+/// Parses the fenced (``` or ~~~) code blocks out of a markdown answer.
+///
+/// An unterminated block at the end of the text is still returned, since
+/// models sometimes stop before closing the fence.
+pub fn parse_code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(String, CodeBlock)> = None;
+    
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match &mut current {
+            Some((fence, block)) => {
+                if trimmed.trim_end() == fence.as_str() {
+                    let (_, mut block) = current.take().unwrap();
+                    if block.filename.is_none() {
+                        block.filename = filename_from_comment(&block.content);
+                    }
+                    blocks.push(block);
+                } else {
+                    block.content.push_str(line);
+                    block.content.push('\n');
+                }
+            }
+            None => {
+                if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                    let fence: String = trimmed.chars().take_while(|c| *c == '`' || *c == '~').collect();
+                    let (language, filename) = parse_info_string(&trimmed[fence.len()..]);
+                    current = Some((fence, CodeBlock { language, filename, content: String::new() }));
+                }
+            }
+        }
+    }
+    
+    if let Some((_, mut block)) = current {
+        if block.filename.is_none() {
+            block.filename = filename_from_comment(&block.content);
+        }
+        blocks.push(block);
+    }
+    
+    blocks
+}
+
+/// Splits an info string such as `python title="fetch.py"` or `rust:src/main.rs`.
+fn parse_info_string(info: &str) -> (Option<String>, Option<String>) {
+    let mut words = info.split_whitespace();
+    let Some(first) = words.next() else {
+        return (None, None);
+    };
+    
+    let (language, mut filename) = match first.split_once(':') {
+        Some((lang, path)) if !path.is_empty() => (lang, Some(path.to_string())),
+        _ => (first, None),
+    };
+    
+    for word in words {
+        if let Some((key, value)) = word.split_once('=') {
+            if matches!(key, "title" | "file" | "filename" | "name") {
+                filename = Some(value.trim_matches(|c| c == '"' || c == '\'').to_string());
+            }
+        }
+    }
+    
+    let language = (!language.is_empty()).then(|| language.to_lowercase());
+    (language, filename)
+}
+
+/// Looks for a `# file: name` / `// filename: name` hint on the first line of a block.
+fn filename_from_comment(content: &str) -> Option<String> {
+    let first = content.lines().next()?.trim();
+    let comment = ["//", "#", "--", ";"]
+        .iter()
+        .find_map(|prefix| first.strip_prefix(prefix))?
+        .trim();
+    let (key, value) = comment.split_once(':')?;
+    let key = key.trim().to_lowercase();
+    let value = value.trim();
+    (matches!(key.as_str(), "file" | "filename" | "path") && !value.is_empty() && !value.contains(' '))
+        .then(|| value.to_string())
+}
+
+/// Maps common aliases onto one name so `--extract-code py` matches ```python.
+fn normalize_language(language: &str) -> String {
+    let language = language.to_lowercase();
+    let canonical = match language.as_str() {
+        "py" | "python3" => "python",
+        "sh" | "shell" | "zsh" => "bash",
+        "js" | "node" => "javascript",
+        "ts" => "typescript",
+        "yml" => "yaml",
+        "rs" => "rust",
+        "golang" => "go",
+        "c++" => "cpp",
+        "ps1" | "pwsh" => "powershell",
+        _ => return language,
+    };
+    canonical.to_string()
+}
+
+fn extension_for(language: Option<&str>) -> &'static str {
+    match language.map(normalize_language).as_deref() {
+        Some("python") => "py",
+        Some("bash") => "sh",
+        Some("javascript") => "js",
+        Some("typescript") => "ts",
+        Some("yaml") => "yaml",
+        Some("rust") => "rs",
+        Some("go") => "go",
+        Some("cpp") => "cpp",
+        Some("c") => "c",
+        Some("java") => "java",
+        Some("ruby") => "rb",
+        Some("json") => "json",
+        Some("toml") => "toml",
+        Some("sql") => "sql",
+        Some("html") => "html",
+        Some("css") => "css",
+        Some("dockerfile") => "Dockerfile",
+        Some("powershell") => "ps1",
+        _ => "txt",
+    }
+}
+
+/// Settings for `--extract-code`, `--all-blocks` and `--write-blocks`.
+pub struct CodeExtraction {
+    pub extract: bool,
+    language: Option<String>,
+    all: bool,
+    write_dir: Option<PathBuf>,
+}
+
+impl CodeExtraction {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.extract_code && config.write_blocks.is_none() {
+            return None;
+        }
+        
+        Some(Self {
+            extract: config.extract_code,
+            language: config.extract_language.as_deref().map(normalize_language),
+            all: config.all_blocks || !config.extract_code,
+            write_dir: config.write_blocks
+                .as_ref()
+                .map(|dir| PathBuf::from(shellexpand::tilde(dir).as_ref())),
+        })
+    }
+    
+    /// Picks the blocks matching the requested language: the first one, or all with `--all-blocks`.
+    pub fn select(&self, text: &str) -> Vec<CodeBlock> {
+        let matching = parse_code_blocks(text).into_iter().filter(|block| match &self.language {
+            Some(wanted) => block.language.as_deref().map(normalize_language).as_ref() == Some(wanted),
+            None => true,
+        });
+        
+        if self.all {
+            matching.collect()
+        } else {
+            matching.take(1).collect()
+        }
+    }
+    
+    /// Saves blocks under `--write-blocks DIR`, returning the paths written.
+    pub fn write_blocks(&self, blocks: &[CodeBlock]) -> Result<Vec<PathBuf>> {
+        let Some(dir) = &self.write_dir else {
+            return Ok(Vec::new());
+        };
+        fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Error creating {}: {}", dir.display(), e))?;
+        
+        let mut written = Vec::new();
+        for (index, block) in blocks.iter().enumerate() {
+            // Only the file name of a hint is used so answers can't write outside DIR
+            let name = block.filename
+                .as_deref()
+                .and_then(|hint| Path::new(hint).file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| {
+                    let language = block.language.as_deref().filter(|l| is_safe_language(l)).unwrap_or("block");
+                    format!("{}-{}.{}", language, index + 1, extension_for(block.language.as_deref()))
+                });
+            let path = unique_path(dir.join(name));
+            fs::write(&path, &block.content)
+                .map_err(|e| anyhow::anyhow!("Error writing {}: {}", path.display(), e))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// Whether a language from the info string can be used in a file name.
+///
+/// It comes from model output, so anything that could form a path (`/`, `..`) is refused.
+fn is_safe_language(language: &str) -> bool {
+    !language.is_empty()
+        && language.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+}
+
+/// Appends `-2`, `-3`, ... to the file stem until the path is unused.
+fn unique_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
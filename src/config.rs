@@ -1,20 +1,98 @@
 use anyhow::Result;
 use clap::Parser;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::path::Path;
 use std::{env, fs, io};
 
+use crate::backpressure::OverflowPolicy;
+use crate::cache::ResponseCache;
+use crate::cassette::ReplayMiss;
 use crate::cli::{Cli, Commands};
+use crate::decode::{BinaryInput, InputEncoding};
+use crate::endpoints::{BalanceStrategy, EndpointConfig};
+use crate::limits::TruncateStrategy;
+use crate::output::OutputFormat;
+use crate::redact::RedactRule;
+use crate::table::TableFormat;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Config {
     pub stream: bool,
     pub prompt: String,
     pub buffer_time: u64,
-    pub url: String,
+    pub batch_max_lines: Option<usize>,
+    pub batch_max_bytes: Option<usize>,
+    pub batch_max_tokens: Option<usize>,
+    pub batch_max_latency: Option<f64>,
+    pub flush_on: Vec<String>,
+    pub overflow: OverflowPolicy,
+    pub sample_rate: u64,
+    pub queue_capacity: usize,
+    pub context_max_tokens: Option<usize>,
+    pub keep_recent: usize,
+    pub summary_prompt: String,
+    pub input_encoding: InputEncoding,
+    pub binary: BinaryInput,
+    pub strip_ansi: bool,
+    pub max_line_bytes: usize,
+    pub max_input_bytes: Option<usize>,
+    pub max_input_tokens: Option<usize>,
+    pub truncate: TruncateStrategy,
+    pub record_start: Option<String>,
+    pub record_sep: Option<String>,
+    pub null_separated: bool,
+    pub json_records: bool,
+    pub jsonl_in: bool,
+    pub fields: Vec<String>,
+    pub merge_into: Option<String>,
+    pub table: Option<TableFormat>,
+    pub row_template: Option<String>,
+    pub columns: Vec<String>,
+    pub concurrency: usize,
+    pub collapse: bool,
+    pub collapse_min_count: usize,
+    pub redact: bool,
+    pub redact_rules: Vec<RedactRule>,
+    pub unredact: bool,
+    #[serde(deserialize_with = "string_or_list")]
+    pub url: Vec<String>,
+    pub endpoints: Vec<EndpointConfig>,
+    pub balance: BalanceStrategy,
+    pub failure_threshold: u32,
+    pub cooldown: u64,
     pub model: String,
     pub presets: Vec<String>,
     pub debug: bool,
+    pub keep_alive: Option<String>,
+    pub token_env: Option<String>,
+    pub token_file: Option<String>,
+    pub headers: Vec<String>,
+    pub proxy: Option<String>,
+    pub ca_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub insecure: bool,
+    pub output: OutputFormat,
+    pub raw: bool,
+    pub extract_code: bool,
+    pub extract_language: Option<String>,
+    pub all_blocks: bool,
+    pub write_blocks: Option<String>,
+    pub tee: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub replay_miss: ReplayMiss,
+    pub cache: bool,
+    pub cache_dir: Option<String>,
+    pub cache_ttl: Option<u64>,
+    pub cache_max_bytes: Option<u64>,
+    pub refresh: bool,
+    pub dry_run: bool,
+    pub stats: bool,
+    pub stats_json: Option<String>,
+    pub think: Option<bool>,
+    pub show_thinking: bool,
 }
 
 impl Default for Config {
@@ -23,10 +101,78 @@ impl Default for Config {
             stream: false,
             prompt: "Generate a one line summary of the following text.".to_string(),
             buffer_time: 1,
-            url: "http://localhost:11434".to_string(),
+            batch_max_lines: None,
+            batch_max_bytes: None,
+            batch_max_tokens: None,
+            batch_max_latency: None,
+            flush_on: Vec::new(),
+            overflow: OverflowPolicy::Block,
+            sample_rate: 10,
+            queue_capacity: 100,
+            // Below Ollama's default num_ctx of 2048, so the summary is written before the server truncates
+            context_max_tokens: Some(1500),
+            keep_recent: 2,
+            summary_prompt: "Summarise the conversation so far, keeping every detail needed to interpret the next input.".to_string(),
+            input_encoding: InputEncoding::Auto,
+            binary: BinaryInput::Refuse,
+            strip_ansi: true,
+            max_line_bytes: 1024 * 1024,
+            max_input_bytes: None,
+            max_input_tokens: None,
+            truncate: TruncateStrategy::Error,
+            record_start: None,
+            record_sep: None,
+            null_separated: false,
+            json_records: false,
+            jsonl_in: false,
+            fields: Vec::new(),
+            merge_into: None,
+            table: None,
+            row_template: None,
+            columns: Vec::new(),
+            concurrency: 1,
+            collapse: false,
+            collapse_min_count: 2,
+            redact: false,
+            redact_rules: Vec::new(),
+            unredact: false,
+            url: vec!["http://localhost:11434".to_string()],
+            endpoints: Vec::new(),
+            balance: BalanceStrategy::RoundRobin,
+            failure_threshold: 1,
+            cooldown: 30,
             model: "llama3.2".to_string(), // Updated to match your installed model
             presets: vec!["Generate a one line summary of the following text.".to_string()],
             debug: false,
+            keep_alive: None,
+            token_env: None,
+            token_file: None,
+            headers: Vec::new(),
+            proxy: None,
+            ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            insecure: false,
+            output: OutputFormat::Text,
+            raw: false,
+            extract_code: false,
+            extract_language: None,
+            all_blocks: false,
+            write_blocks: None,
+            tee: None,
+            record: None,
+            replay: None,
+            replay_miss: ReplayMiss::Error,
+            cache: true,
+            cache_dir: None,
+            cache_ttl: Some(7 * 24 * 60 * 60),
+            cache_max_bytes: Some(100 * 1024 * 1024),
+            refresh: false,
+            dry_run: false,
+            stats: false,
+            stats_json: None,
+            think: None,
+            show_thinking: false,
         }
     }
 }
@@ -43,6 +189,11 @@ pub fn load_config() -> Result<Config> {
         crate::cli::generate_completions(shell);
         std::process::exit(0);
     }
+    // The cache commands need the configured cache directory, so they run last
+    let cache_command = match &cli.command {
+        Some(Commands::Cache { action }) => Some(action.clone()),
+        _ => None,
+    };
     
     // Override with CLI args
     config.stream = cli.stream;
@@ -56,24 +207,304 @@ pub fn load_config() -> Result<Config> {
         config.buffer_time = cli.buffer_time;
     }
     
-    if cli.url != "http://localhost:11434" {
+    if let Some(max_lines) = cli.batch_max_lines {
+        config.batch_max_lines = Some(max_lines);
+    }
+    
+    if let Some(max_bytes) = cli.batch_max_bytes {
+        config.batch_max_bytes = Some(max_bytes);
+    }
+    
+    if let Some(max_tokens) = cli.batch_max_tokens {
+        config.batch_max_tokens = Some(max_tokens);
+    }
+    
+    if let Some(batch_max_latency) = cli.batch_max_latency {
+        config.batch_max_latency = Some(batch_max_latency);
+    }
+    
+    config.flush_on.extend(cli.flush_on);
+    
+    if let Some(overflow) = cli.overflow {
+        config.overflow = overflow;
+    }
+    
+    if let Some(sample_rate) = cli.sample_rate {
+        config.sample_rate = sample_rate;
+    }
+    
+    if let Some(queue_capacity) = cli.queue_capacity {
+        config.queue_capacity = queue_capacity;
+    }
+    
+    if let Some(max_tokens) = cli.context_max_tokens {
+        config.context_max_tokens = Some(max_tokens);
+    }
+    
+    if let Some(keep_recent) = cli.keep_recent {
+        config.keep_recent = keep_recent;
+    }
+    
+    if let Some(input_encoding) = cli.input_encoding {
+        config.input_encoding = input_encoding;
+    }
+    
+    if let Some(binary) = cli.binary {
+        config.binary = binary;
+    }
+    
+    if cli.keep_ansi {
+        config.strip_ansi = false;
+    }
+    
+    if let Some(max_line_bytes) = cli.max_line_bytes {
+        config.max_line_bytes = max_line_bytes;
+    }
+    
+    if let Some(max_input_bytes) = cli.max_input_bytes {
+        config.max_input_bytes = Some(max_input_bytes);
+    }
+    
+    if let Some(max_input_tokens) = cli.max_input_tokens {
+        config.max_input_tokens = Some(max_input_tokens);
+    }
+    
+    if let Some(truncate) = cli.truncate {
+        config.truncate = truncate;
+    }
+    
+    if let Some(record_start) = cli.record_start {
+        config.record_start = Some(record_start);
+    }
+    
+    if let Some(record_sep) = cli.record_sep {
+        config.record_sep = Some(record_sep);
+    }
+    
+    if cli.null_separated {
+        config.null_separated = true;
+    }
+    
+    if cli.json_records {
+        config.json_records = true;
+    }
+    
+    if cli.jsonl_in {
+        config.jsonl_in = true;
+    }
+    
+    config.fields.extend(cli.fields);
+    
+    if let Some(merge_into) = cli.merge_into {
+        config.merge_into = Some(merge_into);
+        config.jsonl_in = true;
+    }
+    
+    if cli.csv {
+        config.table = Some(TableFormat::Csv);
+    }
+    
+    if cli.tsv {
+        config.table = Some(TableFormat::Tsv);
+    }
+    
+    if let Some(row_template) = cli.row_template {
+        config.row_template = Some(row_template);
+    }
+    
+    if !cli.columns.is_empty() {
+        config.columns = cli.columns;
+    }
+    
+    if let Some(concurrency) = cli.concurrency {
+        config.concurrency = concurrency;
+    }
+    
+    if cli.collapse {
+        config.collapse = true;
+    }
+    
+    if let Some(min_count) = cli.collapse_min_count {
+        config.collapse_min_count = min_count;
+    }
+    
+    if cli.redact {
+        config.redact = true;
+    }
+    
+    // Rules given on the command line imply --redact
+    for rule in &cli.redact_rules {
+        config.redact_rules.push(RedactRule::parse(rule)?);
+        config.redact = true;
+    }
+    
+    if cli.unredact {
+        config.unredact = true;
+    }
+    
+    if !cli.url.is_empty() {
         config.url = cli.url;
+        // Servers given on the command line replace the endpoint table
+        config.endpoints.clear();
+    }
+    
+    if let Some(balance) = cli.balance {
+        config.balance = balance;
     }
     
     if cli.model != "llama3" {
         config.model = cli.model;
     }
     
+    if let Some(keep_alive) = cli.keep_alive {
+        config.keep_alive = Some(keep_alive);
+    }
+    
+    if let Some(token_env) = cli.token_env {
+        config.token_env = Some(token_env);
+        config.token_file = None;
+    }
+    
+    if let Some(token_file) = cli.token_file {
+        config.token_file = Some(token_file);
+        config.token_env = None;
+    }
+    
+    // CLI headers are added on top of the ones from the config file
+    config.headers.extend(cli.headers);
+    
+    if let Some(proxy) = cli.proxy {
+        config.proxy = Some(proxy);
+    }
+    
+    if let Some(ca_cert) = cli.ca_cert {
+        config.ca_cert = Some(ca_cert);
+    }
+    
+    if let Some(client_cert) = cli.client_cert {
+        config.client_cert = Some(client_cert);
+    }
+    
+    if let Some(client_key) = cli.client_key {
+        config.client_key = Some(client_key);
+    }
+    
+    if cli.insecure {
+        config.insecure = true;
+    }
+    
+    if let Some(output) = cli.output {
+        config.output = output;
+    }
+    
+    if cli.raw {
+        config.raw = true;
+    }
+    
+    if let Some(language) = cli.extract_code {
+        config.extract_code = true;
+        if !language.is_empty() {
+            config.extract_language = Some(language);
+        }
+    }
+    
+    if cli.all_blocks {
+        config.all_blocks = true;
+    }
+    
+    if let Some(write_blocks) = cli.write_blocks {
+        config.write_blocks = Some(write_blocks);
+    }
+    
+    if let Some(tee) = cli.tee {
+        config.tee = Some(tee);
+    }
+    
+    if let Some(record) = cli.record {
+        config.record = Some(record);
+    }
+    
+    if let Some(replay) = cli.replay {
+        config.replay = Some(replay);
+    }
+    
+    if let Some(replay_miss) = cli.replay_miss {
+        config.replay_miss = replay_miss;
+    }
+    
+    if cli.no_cache {
+        config.cache = false;
+    }
+    
+    if let Some(cache_dir) = cli.cache_dir {
+        config.cache_dir = Some(cache_dir);
+    }
+    
+    if let Some(cache_ttl) = cli.cache_ttl {
+        config.cache_ttl = Some(cache_ttl);
+    }
+    
+    if let Some(cache_max_bytes) = cli.cache_max_bytes {
+        config.cache_max_bytes = Some(cache_max_bytes);
+    }
+    
+    if cli.refresh {
+        config.refresh = true;
+    }
+    
+    if cli.dry_run {
+        config.dry_run = true;
+    }
+    
+    if cli.stats {
+        config.stats = true;
+    }
+    
+    if let Some(stats_json) = cli.stats_json {
+        config.stats_json = Some(stats_json);
+    }
+    
+    if let Some(think) = cli.think {
+        config.think = Some(think);
+    }
+    
+    if cli.show_thinking {
+        config.show_thinking = true;
+    }
+    
     config.debug = cli.debug;
     
     if config.debug {
         eprintln!("Configured with model: {}", config.model);
-        eprintln!("Server URL: {}", config.url);
+        eprintln!("Server URL: {}", config.url.join(", "));
+    }
+    
+    if let Some(action) = cache_command {
+        ResponseCache::open(&config).run(&action)?;
+        std::process::exit(0);
     }
     
     Ok(config)
 }
 
+/// Accepts `url = "..."` as well as `url = ["...", "..."]`.
+fn string_or_list<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(url) => vec![url],
+        OneOrMany::Many(urls) => urls,
+    })
+}
+
 fn load_config_file() -> Result<Config> {
     // Check environment variable for config file path
     if let Ok(config_path) = env::var("CONFIG_FILE") {
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use tokio::sync::mpsc;
+
+use crate::app::AppContext;
+use crate::requests::{generate_request, send_request, Chunk};
+use crate::tokens::estimate_tokens;
+
+/// One batch of input and the answer the model gave for it.
+#[derive(Debug, Clone)]
+struct Exchange {
+    input: String,
+    output: String,
+}
+
+/// Keeps the stream-mode conversation inside the model's context window.
+///
+/// Ollama's `context` holds the tokenised conversation, so its length is the
+/// number of tokens in use. Once it passes `context_max_tokens` the model is
+/// asked to summarise the conversation, and the next request starts a fresh
+/// context seeded with that summary plus the most recent exchanges verbatim.
+pub struct Conversation {
+    context: Option<Vec<i32>>,
+    seed: Option<String>,
+    recent: VecDeque<Exchange>,
+    max_tokens: Option<usize>,
+    keep_recent: usize,
+}
+
+impl Conversation {
+    pub fn new(ctx: &AppContext) -> Self {
+        Self {
+            context: None,
+            seed: None,
+            recent: VecDeque::new(),
+            max_tokens: ctx.config.context_max_tokens.filter(|max| *max > 0),
+            keep_recent: ctx.config.keep_recent,
+        }
+    }
+    
+    pub fn context(&self) -> Option<&Vec<i32>> {
+        self.context.as_ref()
+    }
+    
+    /// The prompt to send for `body`, prefixed with the seed after a reset.
+    pub fn prompt_for(&self, body: &str) -> String {
+        match &self.seed {
+            Some(seed) => format!("{}\n\nNew input:\n{}", seed, body),
+            None => body.to_string(),
+        }
+    }
+    
+    /// Estimated tokens the conversation occupies in the model's window.
+    pub fn estimated_tokens(&self) -> usize {
+        match (&self.context, &self.seed) {
+            (Some(context), _) => context.len(),
+            (None, Some(seed)) => estimate_tokens(seed),
+            (None, None) => 0,
+        }
+    }
+    
+    pub fn record(&mut self, input: &str, output: &str, context: Vec<i32>) {
+        self.context = Some(context);
+        self.seed = None;
+        if self.keep_recent > 0 {
+            self.recent.push_back(Exchange {
+                input: input.to_string(),
+                output: output.to_string(),
+            });
+            while self.recent.len() > self.keep_recent {
+                self.recent.pop_front();
+            }
+        }
+    }
+    
+    pub fn needs_summary(&self) -> bool {
+        self.max_tokens.is_some_and(|max| self.estimated_tokens() > max)
+    }
+    
+    /// Asks the model to summarise the conversation and restarts from that summary.
+    pub async fn summarize(&mut self, ctx: &AppContext) -> Result<()> {
+        let Some(context) = &self.context else {
+            return Ok(());
+        };
+        let config = &ctx.config;
+        if config.debug {
+            eprintln!("Context at {} tokens, summarising conversation", context.len());
+        }
+        
+        let (tx, mut rx) = mpsc::channel::<Chunk>(100);
+        let request = generate_request(&config.summary_prompt, config, context);
+        let collector = async {
+            let mut summary = String::new();
+            while let Some(chunk) = rx.recv().await {
+                if let Chunk::Text(text) = chunk {
+                    summary.push_str(&text);
+                }
+            }
+            summary
+        };
+        let (result, summary) = tokio::join!(send_request(request, ctx, tx), collector);
+        result?;
+        
+        let mut seed = format!("Summary of the conversation so far:\n{}", summary.trim());
+        if !self.recent.is_empty() {
+            seed.push_str("\n\nMost recent exchanges:");
+            for exchange in &self.recent {
+                seed.push_str(&format!(
+                    "\n\nInput:\n{}\n\nResponse:\n{}",
+                    exchange.input.trim_end(),
+                    exchange.output.trim_end()
+                ));
+            }
+        }
+        
+        if config.debug {
+            eprintln!("Restarting conversation with a seed of ~{} tokens", estimate_tokens(&seed));
+        }
+        self.context = None;
+        self.seed = Some(seed);
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use encoding_rs::{DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+use crate::config::Config;
+
+/// Character encoding of stdin.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEncoding {
+    /// Use a byte order mark if there is one, detect UTF-16 by its NUL bytes, otherwise UTF-8
+    Auto,
+    Utf8,
+    /// ISO-8859-1 / Windows-1252
+    Latin1,
+    /// UTF-16, little endian unless there is a byte order mark
+    #[value(name = "utf-16", alias = "utf16")]
+    #[serde(rename = "utf-16", alias = "utf16")]
+    Utf16,
+    #[value(name = "utf-16le", alias = "utf16le")]
+    #[serde(rename = "utf-16le", alias = "utf16le")]
+    Utf16le,
+    #[value(name = "utf-16be", alias = "utf16be")]
+    #[serde(rename = "utf-16be", alias = "utf16be")]
+    Utf16be,
+}
+
+/// What happens when the input looks like binary data rather than text.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryInput {
+    /// Stop with an error
+    Refuse,
+    /// Send a hex dump of the start of the data and its size instead
+    Hex,
+    /// Decode it as text anyway
+    Allow,
+}
+
+/// How raw stdin bytes become text.
+#[derive(Debug, Clone, Copy)]
+pub struct InputDecoding {
+    pub encoding: InputEncoding,
+    pub binary: BinaryInput,
+    pub strip_ansi: bool,
+    /// NUL bytes separate records with `-0`, so they don't mark the input as binary
+    pub allow_nul: bool,
+}
+
+impl InputDecoding {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            encoding: config.input_encoding,
+            binary: config.binary,
+            strip_ansi: config.strip_ansi,
+            allow_nul: config.null_separated,
+        }
+    }
+}
+
+/// Bytes looked at before choosing an encoding and checking for binary data.
+const SNIFF_LEN: usize = 4096;
+
+/// Bytes shown in the hex summary of binary input.
+const HEX_LEN: usize = 256;
+
+struct HexSummary {
+    head: Vec<u8>,
+    total: u64,
+}
+
+/// Incrementally converts stdin bytes to UTF-8.
+///
+/// Invalid sequences are replaced with U+FFFD rather than ending the run, and
+/// counted so the caller can report them.
+pub struct Transcoder {
+    settings: InputDecoding,
+    decoder: Option<encoding_rs::Decoder>,
+    sniff: Vec<u8>,
+    hex: Option<HexSummary>,
+    replaced: u64,
+}
+
+impl Transcoder {
+    pub fn new(settings: InputDecoding) -> Self {
+        Self {
+            settings,
+            decoder: None,
+            sniff: Vec::new(),
+            hex: None,
+            replaced: 0,
+        }
+    }
+    
+    /// Number of invalid bytes replaced so far.
+    pub fn replaced(&self) -> u64 {
+        self.replaced
+    }
+    
+    /// Decodes the next chunk of input, returning the text that is complete so far.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<String> {
+        if let Some(hex) = &mut self.hex {
+            hex.push(bytes);
+            return Ok(String::new());
+        }
+        if self.decoder.is_some() {
+            return Ok(self.decode(bytes, false));
+        }
+        
+        // Detection uses the first read so stream mode doesn't wait for more input
+        self.sniff.extend_from_slice(bytes);
+        self.start()
+    }
+    
+    /// Decodes whatever is buffered at the end of input.
+    pub fn finish(&mut self) -> Result<String> {
+        let mut text = String::new();
+        if self.decoder.is_none() && self.hex.is_none() {
+            text = self.start()?;
+        }
+        if let Some(hex) = &self.hex {
+            return Ok(text + &hex.render());
+        }
+        Ok(text + &self.decode(&[], true))
+    }
+    
+    /// Chooses the encoding from the sniffed bytes and decodes them.
+    fn start(&mut self) -> Result<String> {
+        let sample = std::mem::take(&mut self.sniff);
+        let head = &sample[..sample.len().min(SNIFF_LEN)];
+        let encoding = self.detect(head);
+        
+        let single_byte = encoding == UTF_8 || encoding == WINDOWS_1252;
+        if single_byte && looks_binary(head, self.settings.allow_nul) {
+            match self.settings.binary {
+                BinaryInput::Refuse => {
+                    return Err(anyhow::anyhow!(
+                        "Input looks like binary data (use --binary hex to send a hex summary, or --binary allow)"
+                    ));
+                }
+                BinaryInput::Hex => {
+                    let mut hex = HexSummary { head: Vec::new(), total: 0 };
+                    hex.push(&sample);
+                    self.hex = Some(hex);
+                    return Ok(String::new());
+                }
+                BinaryInput::Allow => {}
+            }
+        }
+        
+        self.decoder = Some(encoding.new_decoder_with_bom_removal());
+        Ok(self.decode(&sample, false))
+    }
+    
+    fn detect(&self, sample: &[u8]) -> &'static Encoding {
+        let bom = Encoding::for_bom(sample).map(|(encoding, _)| encoding);
+        match self.settings.encoding {
+            InputEncoding::Utf8 => UTF_8,
+            InputEncoding::Latin1 => WINDOWS_1252,
+            InputEncoding::Utf16le => UTF_16LE,
+            InputEncoding::Utf16be => UTF_16BE,
+            InputEncoding::Utf16 => bom.filter(|e| *e != UTF_8).unwrap_or(UTF_16LE),
+            InputEncoding::Auto => bom
+                .or_else(|| (!self.settings.allow_nul).then(|| utf16_without_bom(sample)).flatten())
+                .unwrap_or(UTF_8),
+        }
+    }
+    
+    fn decode(&mut self, mut bytes: &[u8], last: bool) -> String {
+        let decoder = self.decoder.as_mut().expect("decoder chosen before decoding");
+        let mut out = String::new();
+        loop {
+            let needed = decoder
+                .max_utf8_buffer_length_without_replacement(bytes.len())
+                .unwrap_or(bytes.len() * 3 + 16);
+            out.reserve(needed);
+            let (result, read) = decoder.decode_to_string_without_replacement(bytes, &mut out, last);
+            bytes = &bytes[read..];
+            match result {
+                DecoderResult::InputEmpty => return out,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(bad, _) => {
+                    self.replaced += u64::from(bad);
+                    out.push('\u{FFFD}');
+                }
+            }
+        }
+    }
+}
+
+/// Text written as UTF-16 without a BOM has a NUL in every other byte for ASCII characters.
+fn utf16_without_bom(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if odd * 10 >= pairs * 4 && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 10 >= pairs * 4 && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// NUL bytes, or more than one in ten bytes being control characters, mean binary data.
+fn looks_binary(sample: &[u8], allow_nul: bool) -> bool {
+    if sample.is_empty() {
+        return false;
+    }
+    if !allow_nul && sample.contains(&0) {
+        return true;
+    }
+    let control = sample
+        .iter()
+        .filter(|b| b.is_ascii_control() && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b | 0))
+        .count();
+    control * 10 > sample.len()
+}
+
+impl HexSummary {
+    fn push(&mut self, bytes: &[u8]) {
+        let room = HEX_LEN.saturating_sub(self.head.len());
+        self.head.extend_from_slice(&bytes[..room.min(bytes.len())]);
+        self.total += bytes.len() as u64;
+    }
+    
+    /// An `xxd`-style dump of the first bytes plus the total size.
+    fn render(&self) -> String {
+        let mut out = format!("Binary input of {} bytes, starting with:\n", self.total);
+        for (index, row) in self.head.chunks(16).enumerate() {
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = row
+                .iter()
+                .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+                .collect();
+            out.push_str(&format!("{:08x}  {:<47}  |{}|\n", index * 16, hex.join(" "), ascii));
+        }
+        if self.total > self.head.len() as u64 {
+            out.push_str("...\n");
+        }
+        out
+    }
+}
+
+fn ansi_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        // CSI sequences (colours, cursor movement), OSC sequences (titles, links) and two-byte escapes
+        Regex::new(r"\x1b\[[0-9:;<=>?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-Z\\-_]")
+            .expect("valid ANSI pattern")
+    })
+}
+
+/// Removes terminal escape sequences, as written by tools run with `--color=always`.
+pub fn strip_ansi(text: &str) -> String {
+    if !text.contains('\x1b') {
+        return text.to_string();
+    }
+    ansi_pattern().replace_all(text, "").into_owned()
+}
+
+/// A reader that yields the UTF-8 text of a byte stream in any supported encoding.
+pub struct DecodingReader<R> {
+    inner: R,
+    transcoder: Transcoder,
+    pending: Vec<u8>,
+    offset: usize,
+    eof: bool,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R, settings: InputDecoding) -> Self {
+        Self {
+            inner,
+            transcoder: Transcoder::new(settings),
+            pending: Vec::new(),
+            offset: 0,
+            eof: false,
+        }
+    }
+    
+    pub fn replaced(&self) -> u64 {
+        self.transcoder.replaced()
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.pending.len() && !self.eof {
+            let mut chunk = [0u8; 8192];
+            let read = self.inner.read(&mut chunk)?;
+            let text = if read == 0 {
+                self.eof = true;
+                self.transcoder.finish()
+            } else {
+                self.transcoder.push(&chunk[..read])
+            };
+            let text = text.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            self.pending = text.into_bytes();
+            self.offset = 0;
+        }
+        
+        let available = &self.pending[self.offset..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.offset += count;
+        Ok(count)
+    }
+}
@@ -0,0 +1,90 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Patterns for the variable parts of log lines, most specific first.
+fn masks() -> &'static [(Regex, &'static str)] {
+    static MASKS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    MASKS.get_or_init(|| {
+        [
+            // 2024-05-01T12:00:00.123Z, 2024-05-01 12:00:00,123+02:00
+            (r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?", "<TS>"),
+            // May  1 12:00:00 (syslog)
+            (r"\b(?:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)\s+\d{1,2}\s+\d{2}:\d{2}:\d{2}\b", "<TS>"),
+            (r"\b\d{2}:\d{2}:\d{2}(?:[.,]\d+)?\b", "<TS>"),
+            (r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b", "<UUID>"),
+            (r"\b(?:\d{1,3}\.){3}\d{1,3}(?::\d+)?\b", "<IP>"),
+            (r"\b(?:[0-9a-fA-F]{1,4}:){2,7}[0-9a-fA-F]{1,4}\b", "<IP>"),
+            (r"\b0x[0-9a-fA-F]+\b", "<HEX>"),
+            (r"\b[0-9a-fA-F]{8,}\b", "<HEX>"),
+            (r"\d+(?:\.\d+)?", "<NUM>"),
+        ]
+        .into_iter()
+        .map(|(pattern, mask)| (Regex::new(pattern).expect("valid mask pattern"), mask))
+        .collect()
+    })
+}
+
+/// Replaces timestamps, UUIDs, IPs, hex values and numbers with placeholders.
+pub fn template_of(line: &str) -> String {
+    let mut template = line.to_string();
+    for (re, mask) in masks() {
+        template = re
+            .replace_all(&template, |caps: &regex::Captures| {
+                let matched = &caps[0];
+                // Long runs of hex letters without a digit are ordinary words
+                if *mask == "<HEX>" && !matched.chars().any(|c| c.is_ascii_digit()) {
+                    matched.to_string()
+                } else {
+                    mask.to_string()
+                }
+            })
+            .into_owned();
+    }
+    template
+}
+
+struct Group {
+    template: String,
+    lines: Vec<String>,
+}
+
+/// Collapses lines sharing a template into one summary line per template.
+///
+/// Templates seen at least `min_count` times become
+/// `template ×count (first: ... | last: ...)`; rarer lines are kept as they are.
+/// Groups appear in the order their first line did.
+pub fn collapse(lines: Vec<String>, min_count: usize) -> Vec<String> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    
+    for line in lines {
+        let template = template_of(&line);
+        match index.get(&template) {
+            Some(&i) => groups[i].lines.push(line),
+            None => {
+                index.insert(template.clone(), groups.len());
+                groups.push(Group { template, lines: vec![line] });
+            }
+        }
+    }
+    
+    groups
+        .into_iter()
+        .flat_map(|group| {
+            let count = group.lines.len();
+            if count >= min_count.max(2) {
+                vec![format!(
+                    "{} ×{} (first: {} | last: {})",
+                    group.template,
+                    count,
+                    group.lines[0],
+                    group.lines[count - 1]
+                )]
+            } else {
+                // Below the threshold every original line is kept
+                group.lines
+            }
+        })
+        .collect()
+}
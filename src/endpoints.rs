@@ -0,0 +1,219 @@
+use clap::ValueEnum;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// A server entry from the `[[endpoints]]` table in the config file.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EndpointConfig {
+    pub url: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Models this server can serve; empty means any model
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// How requests are spread across healthy endpoints.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BalanceStrategy {
+    RoundRobin,
+    LeastInFlight,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct Endpoint {
+    pub url: String,
+    weight: u32,
+    models: Vec<String>,
+    in_flight: AtomicUsize,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    fn new(url: &str, weight: u32, models: Vec<String>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            weight: weight.max(1),
+            models,
+            in_flight: AtomicUsize::new(0),
+            health: Mutex::new(Health::default()),
+        }
+    }
+    
+    fn serves(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|m| m == model)
+    }
+    
+    fn unhealthy_until(&self) -> Option<Instant> {
+        self.health.lock().unwrap().unhealthy_until
+    }
+    
+    /// True once an unhealthy endpoint's cooldown has run out and it should be probed.
+    fn needs_probe(&self) -> bool {
+        matches!(self.unhealthy_until(), Some(until) if until <= Instant::now())
+    }
+    
+    fn load(&self) -> f64 {
+        self.in_flight.load(Ordering::SeqCst) as f64 / self.weight as f64
+    }
+    
+    /// Counts a request against this endpoint until the guard is dropped.
+    pub fn start(self: &Arc<Self>) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(Arc::clone(self))
+    }
+}
+
+pub struct InFlightGuard(Arc<Endpoint>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The set of servers a run can talk to, with health tracking and load balancing.
+#[derive(Debug)]
+pub struct EndpointPool {
+    endpoints: Vec<Arc<Endpoint>>,
+    strategy: BalanceStrategy,
+    cursor: AtomicUsize,
+    failure_threshold: u32,
+    cooldown: Duration,
+    debug: bool,
+}
+
+impl EndpointPool {
+    pub fn from_config(config: &Config) -> Self {
+        let endpoints = if config.endpoints.is_empty() {
+            config.url.iter().map(|url| Arc::new(Endpoint::new(url, 1, Vec::new()))).collect()
+        } else {
+            config.endpoints
+                .iter()
+                .map(|e| Arc::new(Endpoint::new(&e.url, e.weight, e.models.clone())))
+                .collect()
+        };
+        
+        Self {
+            endpoints,
+            strategy: config.balance,
+            cursor: AtomicUsize::new(0),
+            failure_threshold: config.failure_threshold.max(1),
+            cooldown: Duration::from_secs(config.cooldown),
+            debug: config.debug,
+        }
+    }
+    
+    pub fn urls(&self) -> Vec<&str> {
+        self.endpoints.iter().map(|e| e.url.as_str()).collect()
+    }
+    
+    /// Endpoints able to serve `model`, in the order they should be tried.
+    ///
+    /// Healthy endpoints come first, ordered by the balancing strategy, followed
+    /// by unhealthy ones so a request still goes out when every server is cooling down.
+    pub fn candidates(&self, model: &str) -> Vec<Arc<Endpoint>> {
+        let now = Instant::now();
+        let serving: Vec<&Arc<Endpoint>> = self.endpoints.iter().filter(|e| e.serves(model)).collect();
+        if serving.is_empty() {
+            return Vec::new();
+        }
+        
+        let (mut healthy, mut unhealthy): (Vec<_>, Vec<_>) = serving
+            .into_iter()
+            .cloned()
+            .partition(|e| e.unhealthy_until().is_none_or(|until| until <= now));
+        
+        match self.strategy {
+            BalanceStrategy::RoundRobin => {
+                let total: u32 = healthy.iter().map(|e| e.weight).sum();
+                if total > 0 {
+                    // Walk a virtual ring where each endpoint occupies `weight` slots
+                    let mut slot = (self.cursor.fetch_add(1, Ordering::SeqCst) % total as usize) as u32;
+                    let mut first = 0;
+                    for (i, endpoint) in healthy.iter().enumerate() {
+                        if slot < endpoint.weight {
+                            first = i;
+                            break;
+                        }
+                        slot -= endpoint.weight;
+                    }
+                    healthy.rotate_left(first);
+                }
+            }
+            BalanceStrategy::LeastInFlight => {
+                healthy.sort_by(|a, b| a.load().total_cmp(&b.load()));
+            }
+        }
+        
+        unhealthy.sort_by_key(|e| e.unhealthy_until());
+        healthy.extend(unhealthy);
+        healthy
+    }
+    
+    /// Checks an endpoint whose cooldown has expired; returns whether it may be used.
+    pub async fn ensure_available(&self, client: &Client, endpoint: &Endpoint) -> bool {
+        if !endpoint.needs_probe() {
+            return true;
+        }
+        
+        let healthy = check_health(client, &endpoint.url).await;
+        if self.debug {
+            eprintln!("Health check for {}: {}", endpoint.url, if healthy { "ok" } else { "failed" });
+        }
+        if healthy {
+            self.mark_success(endpoint);
+        } else {
+            self.mark_failure(endpoint);
+        }
+        healthy
+    }
+    
+    pub fn mark_success(&self, endpoint: &Endpoint) {
+        let mut health = endpoint.health.lock().unwrap();
+        health.failures = 0;
+        health.unhealthy_until = None;
+    }
+    
+    pub fn mark_failure(&self, endpoint: &Endpoint) {
+        let mut health = endpoint.health.lock().unwrap();
+        health.failures += 1;
+        if health.failures >= self.failure_threshold {
+            if self.debug {
+                eprintln!("Marking {} unhealthy for {:?}", endpoint.url, self.cooldown);
+            }
+            health.unhealthy_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+/// Probes `/api/version`, falling back to `/api/tags` for servers that lack it.
+pub async fn check_health(client: &Client, url: &str) -> bool {
+    for path in ["/api/version", "/api/tags"] {
+        let result = client
+            .get(format!("{}{}", url, path))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await;
+        if matches!(result, Ok(r) if r.status().is_success()) {
+            return true;
+        }
+    }
+    false
+}
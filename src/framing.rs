@@ -0,0 +1,414 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::VecDeque;
+use tokio_util::bytes::BytesMut;
+use tokio_util::codec::Decoder;
+
+use crate::config::Config;
+use crate::decode::{strip_ansi, InputDecoding, Transcoder};
+
+/// How stdin is split into the records that are queued, batched and sent.
+#[derive(Debug, Clone)]
+pub enum Framing {
+    /// One record per line (the default)
+    Lines,
+    /// A record starts at every line matching the pattern; other lines continue it
+    RecordStart(Regex),
+    /// Records are separated by an arbitrary string
+    Separator(String),
+    /// Records are separated by NUL bytes, as written by `find -print0`
+    Nul,
+    /// Each record is a JSON value, which may span several lines
+    Json,
+}
+
+impl Framing {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let chosen = [
+            config.record_start.is_some(),
+            config.record_sep.is_some(),
+            config.null_separated,
+            config.json_records,
+        ];
+        if chosen.iter().filter(|set| **set).count() > 1 {
+            return Err(anyhow::anyhow!(
+                "Only one of record_start, record_sep, null_separated and json_records can be used"
+            ));
+        }
+        
+        if let Some(pattern) = &config.record_start {
+            let regex = Regex::new(pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid record_start pattern {:?}: {}", pattern, e))?;
+            return Ok(Self::RecordStart(regex));
+        }
+        if let Some(separator) = &config.record_sep {
+            let separator = unescape(separator);
+            if separator.is_empty() {
+                return Err(anyhow::anyhow!("record_sep must not be empty"));
+            }
+            return Ok(Self::Separator(separator));
+        }
+        if config.null_separated {
+            return Ok(Self::Nul);
+        }
+        // Only explicit JSON records are framed by value; in JSON Lines every line
+        // stands alone, so one malformed line can't swallow the records after it
+        if config.json_records {
+            return Ok(Self::Json);
+        }
+        Ok(Self::Lines)
+    }
+    
+    /// The character input is split on before records are assembled.
+    fn delimiter(&self) -> char {
+        match self {
+            Self::Nul => '\0',
+            _ => '\n',
+        }
+    }
+}
+
+/// Interprets `\n`, `\t`, `\0` and `\\` so separators like `\n\n` can be given on the command line.
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Reassembles lines into records according to a [`Framing`].
+///
+/// A record that grows past `max_bytes` is cut short like an overlong line;
+/// JSON values can't be cut and still parse, so an oversized one is dropped.
+pub struct Framer {
+    framing: Framing,
+    max_bytes: usize,
+    pending: String,
+    /// The current record hit `max_bytes` and the rest of it is being skipped
+    overflowed: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl Framer {
+    pub fn new(framing: Framing, max_bytes: usize) -> Self {
+        Self {
+            framing,
+            max_bytes: max_bytes.max(1),
+            pending: String::new(),
+            overflowed: false,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+        }
+    }
+    
+    /// Feeds one line (without its terminator) and returns the records it completed.
+    pub fn push(&mut self, line: String) -> Vec<String> {
+        match &self.framing {
+            Framing::Lines | Framing::Nul => vec![line],
+            Framing::RecordStart(start) => {
+                if start.is_match(&line) {
+                    self.overflowed = false;
+                    if !self.pending.is_empty() {
+                        let record = std::mem::replace(&mut self.pending, line);
+                        return vec![record];
+                    }
+                }
+                if self.overflowed {
+                    return Vec::new();
+                }
+                if !self.pending.is_empty() {
+                    self.pending.push('\n');
+                }
+                self.pending.push_str(&line);
+                if self.pending.len() > self.max_bytes {
+                    self.cut_pending();
+                }
+                Vec::new()
+            }
+            Framing::Separator(separator) => {
+                let separator = separator.clone();
+                self.pending.push_str(&line);
+                self.pending.push('\n');
+                
+                let mut records = Vec::new();
+                while let Some(pos) = self.pending.find(&separator) {
+                    let record: String = self.pending.drain(..pos + separator.len()).collect();
+                    if std::mem::take(&mut self.overflowed) {
+                        // The rest of a record that was already sent cut short
+                        continue;
+                    }
+                    records.extend(non_empty(&record[..pos]));
+                }
+                
+                if self.pending.len() > self.max_bytes {
+                    if !self.overflowed {
+                        let mut record = std::mem::take(&mut self.pending);
+                        truncate(&mut record, self.max_bytes);
+                        records.extend(non_empty(&record));
+                        eprintln!("Truncated an input record longer than {} bytes", self.max_bytes);
+                        self.overflowed = true;
+                    }
+                    // Keep enough to recognise a separator split across lines
+                    self.keep_tail(separator.len());
+                }
+                records
+            }
+            Framing::Json => {
+                let open = !self.pending.is_empty() || self.overflowed;
+                let blank = line.trim().is_empty();
+                let mut records = Vec::new();
+                // Pretty-printed values indent what they contain, so a blank line or a
+                // bracket at column 0 means the buffered record was malformed: release
+                // it as it is and start over, rather than swallowing the rest of the input
+                if open && (blank || line.starts_with(['{', '['])) {
+                    eprintln!("Ended an unbalanced JSON input record early");
+                    records.extend(self.finish());
+                }
+                if blank {
+                    return records;
+                }
+                self.scan_json(&line);
+                let complete = self.depth == 0 && !self.in_string;
+                
+                if self.overflowed {
+                    self.overflowed = !complete;
+                    return records;
+                }
+                if !self.pending.is_empty() {
+                    self.pending.push('\n');
+                }
+                self.pending.push_str(&line);
+                
+                // A value is complete once every bracket it opened is closed again
+                if complete {
+                    records.push(std::mem::take(&mut self.pending));
+                } else if self.pending.len() > self.max_bytes {
+                    eprintln!("Dropped a JSON input record longer than {} bytes", self.max_bytes);
+                    self.pending.clear();
+                    self.overflowed = true;
+                }
+                records
+            }
+        }
+    }
+    
+    /// Cuts the record being assembled down to `max_bytes` and skips the rest of it.
+    fn cut_pending(&mut self) {
+        truncate(&mut self.pending, self.max_bytes);
+        eprintln!("Truncated an input record longer than {} bytes", self.max_bytes);
+        self.overflowed = true;
+    }
+    
+    /// Drops all but the last `len` bytes of the record being skipped.
+    fn keep_tail(&mut self, len: usize) {
+        let mut start = self.pending.len().saturating_sub(len);
+        while !self.pending.is_char_boundary(start) {
+            start -= 1;
+        }
+        self.pending.drain(..start);
+    }
+    
+    /// Releases a `record_start` record that is only waiting for the next start line.
+    ///
+    /// Called once input has gone quiet, so the last event of a live stream is
+    /// not held back indefinitely. Other framings only complete on a delimiter.
+    pub fn flush_idle(&mut self) -> Option<String> {
+        match self.framing {
+            Framing::RecordStart(_) => {
+                let rest = std::mem::take(&mut self.pending);
+                non_empty(&rest)
+            }
+            _ => None,
+        }
+    }
+    
+    /// Returns the record still being assembled when input ends.
+    pub fn finish(&mut self) -> Option<String> {
+        self.overflowed = false;
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        let rest = std::mem::take(&mut self.pending);
+        non_empty(&rest)
+    }
+    
+    fn scan_json(&mut self, line: &str) {
+        for c in line.chars() {
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => self.in_string = true,
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Shortens `text` to at most `max_bytes`, on a character boundary.
+fn truncate(text: &mut String, max_bytes: usize) {
+    if text.len() > max_bytes {
+        let mut end = max_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+}
+
+fn non_empty(record: &str) -> Option<String> {
+    let trimmed = record.trim_matches(['\n', '\r']);
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+/// Splits stdin into records for `FramedRead`.
+///
+/// Bytes are decoded with a [`Transcoder`] first, so the delimiter is found in
+/// text rather than raw bytes (which matters for UTF-16). Lines and records
+/// longer than `max_line_bytes` are cut short instead of being buffered without limit.
+pub struct RecordCodec {
+    transcoder: Transcoder,
+    framer: Framer,
+    delimiter: char,
+    strip_ansi: bool,
+    max_line_bytes: usize,
+    text: String,
+    truncating: bool,
+    ready: VecDeque<String>,
+    finished: bool,
+}
+
+impl RecordCodec {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let framing = Framing::from_config(config)?;
+        let decoding = InputDecoding::from_config(config);
+        Ok(Self {
+            transcoder: Transcoder::new(decoding),
+            delimiter: framing.delimiter(),
+            // Multi-line records are held to the same limit as single lines
+            framer: Framer::new(framing, config.max_line_bytes),
+            strip_ansi: decoding.strip_ansi,
+            max_line_bytes: config.max_line_bytes.max(1),
+            text: String::new(),
+            truncating: false,
+            ready: VecDeque::new(),
+            finished: false,
+        })
+    }
+    
+    /// Moves every complete line out of the decoded text and through the framer.
+    fn split_lines(&mut self) {
+        while let Some(pos) = self.text.find(self.delimiter) {
+            let mut line: String = self.text.drain(..pos + self.delimiter.len_utf8()).collect();
+            line.pop();
+            if self.truncating {
+                // The start of this line was already sent
+                self.truncating = false;
+                continue;
+            }
+            let line = self.truncate(line);
+            self.push_line(line);
+        }
+        
+        // Don't wait for the end of an overlong line before sending its start
+        if self.text.len() > self.max_line_bytes && !self.truncating {
+            let rest = std::mem::take(&mut self.text);
+            let line = self.truncate(rest);
+            self.push_line(line);
+            self.truncating = true;
+        }
+        if self.truncating {
+            self.text.clear();
+        }
+    }
+    
+    fn truncate(&self, mut line: String) -> String {
+        if line.len() > self.max_line_bytes {
+            truncate(&mut line, self.max_line_bytes);
+            eprintln!("Truncated an input line longer than {} bytes", self.max_line_bytes);
+        }
+        line
+    }
+    
+    /// The record held back by the framer while waiting for more input, if it can be sent as is.
+    pub fn flush_idle(&mut self) -> Option<String> {
+        self.framer.flush_idle()
+    }
+    
+    fn push_line(&mut self, mut line: String) {
+        if self.delimiter == '\n' && line.ends_with('\r') {
+            line.pop();
+        }
+        if self.strip_ansi {
+            line = strip_ansi(&line);
+        }
+        self.ready.extend(self.framer.push(line));
+    }
+}
+
+impl Decoder for RecordCodec {
+    type Item = String;
+    type Error = anyhow::Error;
+    
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<String>> {
+        if let Some(record) = self.ready.pop_front() {
+            return Ok(Some(record));
+        }
+        if !src.is_empty() {
+            let bytes = src.split();
+            let text = self.transcoder.push(&bytes)?;
+            self.text.push_str(&text);
+            self.split_lines();
+        }
+        Ok(self.ready.pop_front())
+    }
+    
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<String>> {
+        if let Some(record) = self.decode(src)? {
+            return Ok(Some(record));
+        }
+        if !self.finished {
+            self.finished = true;
+            let text = self.transcoder.finish()?;
+            self.text.push_str(&text);
+            self.split_lines();
+            
+            // The last line may not end with a delimiter
+            let rest = std::mem::take(&mut self.text);
+            if !rest.is_empty() && !self.truncating {
+                self.push_line(rest);
+            }
+            self.ready.extend(self.framer.finish());
+            
+            let replaced = self.transcoder.replaced();
+            if replaced > 0 {
+                eprintln!("Replaced {} invalid bytes in the input with U+FFFD", replaced);
+            }
+        }
+        Ok(self.ready.pop_front())
+    }
+}
@@ -1,75 +1,89 @@
 use anyhow::Result;
-use std::io::{self, Write};
-use std::time::Duration;
+use csv::StringRecord;
+use futures::stream::{self, StreamExt};
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncRead;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio::time;
+use tokio_util::sync::CancellationToken;
 
-use crate::config::Config;
-use crate::requests::{generate_first_request, generate_request, send_request};
-use crate::streams::{oneshot_read_stdin, stream_read_stdin};
+use crate::app::AppContext;
+use crate::backpressure::InputQueue;
+use crate::batching::{BatchPolicy, Batcher};
+use crate::conversation::Conversation;
+use crate::decode::InputDecoding;
+use crate::framing::RecordCodec;
+use crate::limits::InputLimit;
+use crate::output::{Destination, OutputFormat, OutputSink};
+use crate::redact::Unredactor;
+use crate::requests::{generate_first_request, generate_request, send_request, Chunk, Generation};
+use crate::signals::ActiveGeneration;
+use crate::stats::StatsReporter;
+use crate::streams::{oneshot_read, stream_read, table_read};
+use crate::table::{write_record, OutputColumn, RowTemplate, TableSettings};
 
-pub async fn oneshot_handler(config: &Config) -> Result<()> {
+pub async fn oneshot_handler<R: AsyncRead + Unpin>(ctx: Arc<AppContext>, input: R, output: Destination) -> Result<()> {
+    let config = &ctx.config;
+    
     // Print debug info if enabled
     if config.debug {
         eprintln!("Starting oneshot handler");
         eprintln!("Using model: {}", config.model);
-        eprintln!("Using URL: {}", config.url);
+        eprintln!("Using URL: {}", ctx.endpoints.urls().join(", "));
     }
     
-    // Read all input from stdin
-    let input = oneshot_read_stdin().await?;
+    // Read all input
+    let codec = RecordCodec::from_config(config)?;
+    let shutdown = ctx.cancellation.shutdown_token();
+    let records = oneshot_read(input, codec, InputLimit::from_config(config), shutdown).await?;
     if config.debug {
-        eprintln!("Read {} lines from stdin", input.len());
+        eprintln!("Read {} records of input", records.len());
     }
-    let joined_input = input.join("\n");
+    let joined_input = ctx.preprocessor.apply(records).join("\n");
+    
+    let mut sink = OutputSink::new(config, ctx.preprocessor.redactor(), output)?;
+    let joined_input = ctx.preprocessor.redact(&joined_input);
+    sink.begin(&joined_input);
     
     // Set up channel for response chunks
-    let (tx, mut rx) = mpsc::channel::<String>(100);
-    
-    // Clone necessary data for the async task
-    let model = config.model.clone();
-    let prompt = config.prompt.clone();
-    let url = config.url.clone();
-    let debug = config.debug;
-    let config_clone = Config {
-        model,
-        prompt,
-        url,
-        buffer_time: config.buffer_time,
-        stream: config.stream,
-        presets: config.presets.clone(),
-        debug,
-    };
+    let (tx, mut rx) = mpsc::channel::<Chunk>(100);
     
     // Create and send request - move the joined_input into the task
-    let input_clone = joined_input.clone();
+    let task_ctx = Arc::clone(&ctx);
     if config.debug {
         eprintln!("Sending request to LLM...");
     }
+    let active = ctx.cancellation.begin_generation();
     let sender_task = tokio::spawn(async move {
-        let request = generate_first_request(&input_clone, &config_clone);
-        send_request(request, &config_clone, tx).await
+        let request = generate_first_request(&joined_input, &task_ctx.config);
+        send_request(request, &task_ctx, tx).await
     });
     
     // Output handler
-    let mut stdout = io::stdout();
-    let mut output = String::new();
     if config.debug {
         eprintln!("Waiting for response chunks...");
     }
     
-    while let Some(chunk) = rx.recv().await {
-        if config.debug {
-            eprintln!("Received chunk of length: {}", chunk.len());
+    while let Some(chunk) = next_chunk(&mut rx, &active).await {
+        match chunk {
+            Chunk::Text(text) => {
+                if config.debug {
+                    eprintln!("Received chunk of length: {}", text.len());
+                }
+                sink.write_chunk(&text)?;
+            }
+            Chunk::Thinking(text) => sink.write_thinking(&text)?,
         }
-        output.push_str(&chunk);
-        stdout.write_all(chunk.as_bytes())?;
-        stdout.flush()?;
     }
     
-    // Make sure we have a final newline
-    if !output.is_empty() && !output.ends_with('\n') {
-        stdout.write_all(b"\n")?;
+    if active.is_cancelled() {
+        // Dropping the request closes the connection, which stops the server generating
+        sender_task.abort();
+        sink.finish(Err(&anyhow::anyhow!("Generation cancelled")))?;
+        return Ok(());
     }
     
     // Check for errors from sender task
@@ -77,12 +91,17 @@ pub async fn oneshot_handler(config: &Config) -> Result<()> {
         eprintln!("Waiting for sender task to complete...");
     }
     match sender_task.await {
-        Ok(Ok(_)) => {
+        Ok(Ok(generation)) => {
             if config.debug {
                 eprintln!("Request completed successfully");
             }
+            sink.finish(Ok(&generation))?;
+            StatsReporter::new(config).record(&generation)?;
+        },
+        Ok(Err(e)) => {
+            eprintln!("Request failed: {}", e);
+            sink.finish(Err(&e))?;
         },
-        Ok(Err(e)) => eprintln!("Request failed: {}", e),
         Err(e) => eprintln!("Sender task failed: {}", e),
     }
     
@@ -92,142 +111,467 @@ pub async fn oneshot_handler(config: &Config) -> Result<()> {
     Ok(())
 }
 
-pub async fn stream_handler(config: &Config) -> Result<()> {
+pub async fn stream_handler<R>(ctx: Arc<AppContext>, input: R, output: Destination) -> Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let config = &ctx.config;
+    
     // Print debug info if enabled
     if config.debug {
         eprintln!("Starting stream handler");
         eprintln!("Using model: {}", config.model);
-        eprintln!("Using URL: {}", config.url);
+        eprintln!("Using URL: {}", ctx.endpoints.urls().join(", "));
     }
     
-    // Set up channel for input stream from stdin
-    let (stdin_tx, mut stdin_rx) = mpsc::channel::<String>(100);
+    // Pretty JSON is one document per batch, which a stream of them would not be
+    if config.output == OutputFormat::Json {
+        return Err(anyhow::anyhow!("--output json cannot be used with --stream; use --output jsonl for one object per batch"));
+    }
     
-    // Spawn task to read from stdin
-    tokio::spawn(async move {
-        if let Err(e) = stream_read_stdin(stdin_tx).await {
-            eprintln!("Error reading stdin: {}", e);
-        }
-    });
+    // Set up queue for the input stream
+    let queue = Arc::new(InputQueue::new(config));
+    let codec = RecordCodec::from_config(config)?;
+    let policy = BatchPolicy::from_config(config)?;
+    let record_idle = policy.record_idle();
+    let mut batcher = Batcher::new(policy);
     
-    let mut full_body = Vec::new();
-    let mut context: Option<Vec<i32>> = None;
-    let timeout = Duration::from_secs(config.buffer_time);
-    let mut stdout = io::stdout();
-    
-    // Clone config for use in the loop
-    let model = config.model.clone();
-    let prompt = config.prompt.clone();
-    let url = config.url.clone();
-    let debug = config.debug;
-    let config_clone = Config {
-        model,
-        prompt,
-        url,
-        buffer_time: config.buffer_time,
-        stream: config.stream,
-        presets: config.presets.clone(),
-        debug,
-    };
+    // Spawn task to read the input
+    let reader = spawn_reader(input, Arc::clone(&queue), codec, record_idle, ctx.cancellation.shutdown_token());
+    
+    let mut conversation = Conversation::new(&ctx);
+    let mut stats = StatsReporter::new(config);
+    let mut sink = OutputSink::new(config, ctx.preprocessor.redactor(), output)?;
     
     if config.debug {
         eprintln!("Entering main processing loop...");
     }
     loop {
-        tokio::select! {
-            Some(line) = stdin_rx.recv() => {
-                if config.debug {
-                    eprintln!("Received input line: {}", line);
-                }
-                full_body.push(line);
-            }
-            _ = time::sleep(timeout) => {
-                if full_body.is_empty() {
+        // Only wait for a deadline while something is buffered
+        let deadline = batcher.deadline();
+        let flush = tokio::select! {
+            line = queue.recv() => match line {
+                Some(line) => {
                     if config.debug {
-                        eprintln!("No input received, continuing...");
-                    }
-                    continue;
-                }
-                
-                let joined_input = full_body.join("\n");
-                if config.debug {
-                    eprintln!("Processing {} lines of input", full_body.len());
-                }
-                full_body.clear();
-                
-                // Set up channel for response chunks
-                let (tx, mut rx) = mpsc::channel::<String>(100);
-                
-                // Create a complete clone of all data needed for the task
-                let task_config = config_clone.clone();
-                let task_input = joined_input.clone();
-                let task_context = context.clone();
-                
-                // Create and send the request
-                if config.debug {
-                    eprintln!("Sending request to LLM...");
-                }
-                let sender_task = tokio::spawn(async move {
-                    match task_context {
-                        Some(ctx) => {
-                            if task_config.debug {
-                                eprintln!("Using existing context of length {}", ctx.len());
-                            }
-                            let ctx_clone = ctx.clone();
-                            let request = generate_request(&task_input, &task_config, &ctx_clone);
-                            send_request(request, &task_config, tx).await
-                        },
-                        None => {
-                            if task_config.debug {
-                                eprintln!("No existing context, starting new conversation");
-                            }
-                            let request = generate_first_request(&task_input, &task_config);
-                            send_request(request, &task_config, tx).await
-                        }
+                        eprintln!("Received input record: {}", line);
                     }
-                });
-                
-                // Output response chunks
-                let mut output = String::new();
-                if config.debug {
-                    eprintln!("Waiting for response chunks...");
+                    batcher.push(line)
                 }
-                while let Some(chunk) = rx.recv().await {
+                None => {
                     if config.debug {
-                        eprintln!("Received chunk of length: {}", chunk.len());
+                        eprintln!("End of input reached");
+                    }
+                    if !batcher.is_empty() {
+                        stats.set_input_counters(queue.counters());
+                        process_batch(&ctx, batcher.take(), &mut conversation, &mut sink, &mut stats).await?;
                     }
-                    output.push_str(&chunk);
-                    stdout.write_all(chunk.as_bytes())?;
-                    stdout.flush()?;
+                    // Input that could not be read (e.g. refused binary data) fails the run
+                    return reader_result(reader).await;
+                }
+            },
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => true,
+        };
+        
+        if flush && !batcher.is_empty() {
+            if config.debug {
+                eprintln!("Processing {} lines of input", batcher.len());
+            }
+            stats.set_input_counters(queue.counters());
+            process_batch(&ctx, batcher.take(), &mut conversation, &mut sink, &mut stats).await?;
+        }
+    }
+}
+
+/// Sends one buffered batch, continuing the conversation.
+async fn process_batch(
+    ctx: &Arc<AppContext>,
+    lines: Vec<String>,
+    conversation: &mut Conversation,
+    sink: &mut OutputSink,
+    stats: &mut StatsReporter,
+) -> Result<()> {
+    let config = &ctx.config;
+    let lines = ctx.preprocessor.apply(lines);
+    if lines.is_empty() {
+        // Nothing left once the records without selected fields were dropped
+        return Ok(());
+    }
+    let joined_input = lines.join("\n");
+    let joined_input = ctx.preprocessor.redact(&joined_input);
+    sink.begin(&joined_input);
+    
+    // Output response chunks as they arrive
+    let generated = converse(ctx, conversation, &joined_input, |chunk| match chunk {
+        Chunk::Text(text) => {
+            if config.debug {
+                eprintln!("Received chunk of length: {}", text.len());
+            }
+            sink.write_chunk(&text)
+        }
+        Chunk::Thinking(text) => sink.write_thinking(&text),
+    })
+    .await;
+    
+    match generated {
+        Ok(Some((output, generation))) => {
+            if config.debug {
+                eprintln!("Request completed with context of length {}", generation.context.len());
+            }
+            sink.finish(Ok(&generation))?;
+            stats.record(&generation)?;
+            conversation.record(&joined_input, &output, generation.context);
+            
+            // Keep the conversation inside the model's window before the next batch
+            if conversation.needs_summary() {
+                if let Err(e) = conversation.summarize(ctx).await {
+                    eprintln!("Error summarising conversation: {}", e);
                 }
-                
-                // Make sure we have a final newline
-                if !output.is_empty() && !output.ends_with('\n') {
-                    stdout.write_all(b"\n")?;
-                    stdout.flush()?;
+            }
+            Ok(())
+        }
+        Ok(None) => {
+            // A cancelled batch is left out of the conversation and the stream carries on
+            sink.finish(Err(&anyhow::anyhow!("Generation cancelled")))?;
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Error sending request: {}", e);
+            sink.finish(Err(&e))?;
+            Err(anyhow::anyhow!("Error sending request: {}", e))
+        }
+    }
+}
+
+/// Sends `body` as the next turn of the conversation, passing each chunk to `on_chunk`.
+///
+/// Returns the answer text and what the request left behind, or `None` if the
+/// generation was cancelled. The conversation itself is not updated.
+pub async fn converse(
+    ctx: &Arc<AppContext>,
+    conversation: &Conversation,
+    body: &str,
+    mut on_chunk: impl FnMut(Chunk) -> Result<()>,
+) -> Result<Option<(String, Generation)>> {
+    let config = &ctx.config;
+    
+    // Set up channel for response chunks
+    let (tx, mut rx) = mpsc::channel::<Chunk>(100);
+    
+    // The task shares the client and config through the context
+    let task_ctx = Arc::clone(ctx);
+    let task_context = conversation.context().cloned();
+    let task_prompt = conversation.prompt_for(body);
+    
+    // Create and send the request
+    if config.debug {
+        eprintln!("Sending request to LLM...");
+    }
+    let active = ctx.cancellation.begin_generation();
+    let sender_task = tokio::spawn(async move {
+        match task_context {
+            Some(history) => {
+                if task_ctx.config.debug {
+                    eprintln!("Using existing context of length {}", history.len());
                 }
-                
-                // Update context for next request
-                if config.debug {
-                    eprintln!("Waiting for sender task to complete...");
+                let request = generate_request(&task_prompt, &task_ctx.config, &history);
+                send_request(request, &task_ctx, tx).await
+            },
+            None => {
+                if task_ctx.config.debug {
+                    eprintln!("No existing context, starting new conversation");
                 }
-                match sender_task.await {
-                    Ok(Ok(ctx)) => {
-                        if config.debug {
-                            eprintln!("Request completed with context of length {}", ctx.len());
-                        }
-                        context = Some(ctx);
-                    },
-                    Ok(Err(e)) => {
-                        eprintln!("Error sending request: {}", e);
-                        return Err(anyhow::anyhow!("Error sending request: {}", e));
-                    }
+                let request = generate_first_request(&task_prompt, &task_ctx.config);
+                send_request(request, &task_ctx, tx).await
+            }
+        }
+    });
+    
+    if config.debug {
+        eprintln!("Waiting for response chunks...");
+    }
+    let mut output = String::new();
+    while let Some(chunk) = next_chunk(&mut rx, &active).await {
+        if let Chunk::Text(text) = &chunk {
+            output.push_str(text);
+        }
+        if let Err(e) = on_chunk(chunk) {
+            sender_task.abort();
+            return Err(e);
+        }
+    }
+    
+    if active.is_cancelled() {
+        sender_task.abort();
+        return Ok(None);
+    }
+    
+    if config.debug {
+        eprintln!("Waiting for sender task to complete...");
+    }
+    match sender_task.await {
+        Ok(Ok(generation)) => Ok(Some((output, generation))),
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(anyhow::anyhow!("Task error: {}", e)),
+    }
+}
+
+/// Calls the model once per JSON record and re-emits each record with the answer merged in.
+pub async fn enrich_handler<R>(ctx: Arc<AppContext>, input: R, output: Destination) -> Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let config = &ctx.config;
+    
+    if config.debug {
+        eprintln!("Starting enrich handler");
+        eprintln!("Using model: {}", config.model);
+        eprintln!("Using URL: {}", ctx.endpoints.urls().join(", "));
+    }
+    
+    let json = ctx.preprocessor
+        .json_input()
+        .ok_or_else(|| anyhow::anyhow!("merge_into requires JSON input"))?;
+    
+    // Records are read the same way as in stream mode, so this also works on `tail -f`
+    let queue = Arc::new(InputQueue::new(config));
+    let codec = RecordCodec::from_config(config)?;
+    let record_idle = BatchPolicy::from_config(config)?.record_idle();
+    let reader = spawn_reader(input, Arc::clone(&queue), codec, record_idle, ctx.cancellation.shutdown_token());
+    
+    let mut stats = StatsReporter::new(config);
+    let mut sink = OutputSink::new(config, None, output)?;
+    
+    while let Some(record) = queue.recv().await {
+        let value = match serde_json::from_str(&record) {
+            Ok(value) => value,
+            Err(e) => {
+                // Pass the line through so a stray non-JSON line doesn't break the pipeline
+                eprintln!("Skipping invalid JSON record: {}", e);
+                sink.write_line(&record)?;
+                continue;
+            }
+        };
+        
+        let input = json.select_value(&value);
+        if input.trim().is_empty() {
+            if config.debug {
+                eprintln!("No selected fields in record, passing it through");
+            }
+            sink.write_line(&record)?;
+            continue;
+        }
+        
+        let input = ctx.preprocessor.redact(&input);
+        stats.set_input_counters(queue.counters());
+        let (answer, result) = collect_answer(&ctx, input, None, Some(&mut sink)).await;
+        if config.dry_run {
+            sink.write_report(&answer)?;
+            continue;
+        }
+        match result {
+            Ok(generation) => {
+                stats.record(&generation)?;
+                match json.merge(value, &unredact_answer(&ctx, answer)) {
+                    Ok(merged) => sink.write_line(&merged)?,
                     Err(e) => {
-                        eprintln!("Task error: {}", e);
-                        return Err(anyhow::anyhow!("Task error: {}", e));
+                        eprintln!("Error merging answer: {}", e);
+                        sink.write_line(&record)?;
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Request failed: {}", e);
+                sink.write_line(&record)?;
+            }
+        }
+    }
+    
+    if config.debug {
+        eprintln!("Handler completed");
+    }
+    reader_result(reader).await
+}
+
+/// Reads records into `queue` on a separate task, closing the queue when input ends or fails.
+fn spawn_reader<R>(
+    input: R,
+    queue: Arc<InputQueue>,
+    codec: RecordCodec,
+    idle: Duration,
+    shutdown: CancellationToken,
+) -> JoinHandle<Result<()>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let result = stream_read(input, &queue, codec, idle, shutdown).await;
+        queue.close();
+        result
+    })
+}
+
+/// The error that ended the input early, once the queued records have been handled.
+async fn reader_result(reader: JoinHandle<Result<()>>) -> Result<()> {
+    match reader.await {
+        Ok(result) => result,
+        Err(e) => Err(anyhow::anyhow!("Task error: {}", e)),
+    }
+}
+
+/// Calls the model once per table row and writes the table back out with the answers appended.
+pub async fn table_handler<R>(ctx: Arc<AppContext>, input: R, output: Destination) -> Result<()>
+where
+    R: Read + Send + 'static,
+{
+    let config = &ctx.config;
+    let settings = TableSettings::from_config(config)
+        .ok_or_else(|| anyhow::anyhow!("No table format configured"))?;
+    
+    if config.debug {
+        eprintln!("Starting table handler");
+        eprintln!("Using model: {}", config.model);
+        eprintln!("Using URL: {}", ctx.endpoints.urls().join(", "));
+        eprintln!("Answering {} column(s) with concurrency {}", settings.columns.len(), settings.concurrency);
+    }
+    
+    // The csv reader is blocking, so it runs on its own thread
+    let (tx, mut rx) = mpsc::channel::<Result<StringRecord>>(settings.concurrency * 2);
+    let format = settings.format;
+    let decoding = InputDecoding::from_config(config);
+    tokio::task::spawn_blocking(move || table_read(input, format, decoding, tx));
+    
+    let Some(headers) = rx.recv().await.transpose()? else {
+        return Ok(());
+    };
+    let template = RowTemplate::new(settings.template.as_deref(), headers.clone())?;
+    
+    let mut sink = OutputSink::new(config, None, output)?;
+    let mut stats = StatsReporter::new(config);
+    let mut header_out = headers;
+    for column in &settings.columns {
+        header_out.push_field(&column.name);
+    }
+    sink.write_line(&write_record(format, &header_out)?)?;
+    
+    // Up to `concurrency` rows are in flight, but rows are written in input order
+    let shutdown = ctx.cancellation.shutdown_token();
+    let rows = stream::poll_fn(|cx| rx.poll_recv(cx)).take_until(shutdown.cancelled());
+    let answered = rows
+        .map(|row| answer_row(&ctx, &template, &settings.columns, row))
+        .buffered(settings.concurrency);
+    tokio::pin!(answered);
+    
+    let mut row_number = 0;
+    while let Some(result) = answered.next().await {
+        let (mut row, answers) = result?;
+        row_number += 1;
+        if config.dry_run {
+            for (report, _) in answers {
+                sink.write_report(&report)?;
+            }
+            continue;
+        }
+        for (answer, result) in answers {
+            match result {
+                Ok(generation) => stats.record(&generation)?,
+                Err(e) => eprintln!("Request failed for row {}: {}", row_number, e),
+            }
+            row.push_field(&answer);
+        }
+        sink.write_line(&write_record(format, &row)?)?;
+    }
+    
+    if config.debug {
+        eprintln!("Handler completed");
+    }
+    Ok(())
+}
+
+/// Answers every output column for one row; failed requests leave the cell empty.
+async fn answer_row(
+    ctx: &Arc<AppContext>,
+    template: &RowTemplate,
+    columns: &[OutputColumn],
+    row: Result<StringRecord>,
+) -> Result<(StringRecord, Vec<(String, Result<Generation>)>)> {
+    let row = row?;
+    let body = ctx.preprocessor.redact(&template.render(&row));
+    
+    let mut answers = Vec::new();
+    for column in columns {
+        let (answer, result) = collect_answer(ctx, body.clone(), column.prompt.clone(), None).await;
+        let answer = match result {
+            Ok(_) => unredact_answer(ctx, answer).trim().to_string(),
+            Err(_) => String::new(),
+        };
+        answers.push((answer, result));
+    }
+    Ok((row, answers))
+}
+
+/// Restores redacted values in a complete answer when `--unredact` is set.
+pub fn unredact_answer(ctx: &AppContext, answer: String) -> String {
+    // A dry run's report shows the request as sent, placeholders and all
+    match ctx.preprocessor.redactor().filter(|_| ctx.config.unredact && !ctx.config.dry_run) {
+        Some(redactor) => {
+            let mut unredactor = Unredactor::new(redactor);
+            let restored = unredactor.push(&answer);
+            restored + &unredactor.finish()
+        }
+        None => answer,
+    }
+}
+
+/// Sends a single request and collects the whole answer instead of printing it.
+///
+/// `system` replaces the configured prompt; reasoning is only shown when a sink is given.
+pub async fn collect_answer(
+    ctx: &Arc<AppContext>,
+    body: String,
+    system: Option<String>,
+    mut sink: Option<&mut OutputSink>,
+) -> (String, Result<Generation>) {
+    let (tx, mut rx) = mpsc::channel::<Chunk>(100);
+    let task_ctx = Arc::clone(ctx);
+    let active = ctx.cancellation.begin_generation();
+    let sender_task = tokio::spawn(async move {
+        let mut request = generate_first_request(&body, &task_ctx.config);
+        if let Some(system) = &system {
+            request.system = system;
+        }
+        send_request(request, &task_ctx, tx).await
+    });
+    
+    let mut answer = String::new();
+    while let Some(chunk) = next_chunk(&mut rx, &active).await {
+        match chunk {
+            Chunk::Text(text) => answer.push_str(&text),
+            Chunk::Thinking(text) => {
+                if let Some(sink) = sink.as_deref_mut() {
+                    if let Err(e) = sink.write_thinking(&text) {
+                        return (answer, Err(e));
                     }
                 }
             }
         }
     }
+    
+    if active.is_cancelled() {
+        sender_task.abort();
+        return (answer, Err(anyhow::anyhow!("Generation cancelled")));
+    }
+    
+    let result = match sender_task.await {
+        Ok(result) => result,
+        Err(e) => Err(anyhow::anyhow!("Task error: {}", e)),
+    };
+    (answer, result)
+}
+
+/// Receives the next response chunk, or `None` once the response ends or is cancelled.
+async fn next_chunk(rx: &mut mpsc::Receiver<Chunk>, active: &ActiveGeneration<'_>) -> Option<Chunk> {
+    tokio::select! {
+        chunk = rx.recv() => chunk,
+        _ = active.cancelled() => None,
+    }
 }
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+
+/// A field of a JSON record, given as a JSON pointer (`/request/path`) or a
+/// dotted path (`request.path`, `$.items[0].name`).
+#[derive(Debug, Clone)]
+pub struct FieldPath {
+    name: String,
+    tokens: Vec<String>,
+}
+
+impl FieldPath {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let tokens: Vec<String> = if let Some(pointer) = spec.strip_prefix('/') {
+            pointer
+                .split('/')
+                .map(|token| token.replace("~1", "/").replace("~0", "~"))
+                .collect()
+        } else {
+            let path = spec.strip_prefix('$').unwrap_or(spec);
+            let path = path.strip_prefix('.').unwrap_or(path);
+            path.split('.')
+                .flat_map(|part| {
+                    // items[0] addresses the first element of the items array
+                    part.split(['[', ']']).filter(|t| !t.is_empty()).map(str::to_string)
+                })
+                .collect()
+        };
+        
+        if tokens.is_empty() || tokens.iter().any(String::is_empty) {
+            return Err(anyhow::anyhow!("Invalid field path {:?}", spec));
+        }
+        Ok(Self {
+            name: spec.trim_start_matches(['$', '.', '/']).to_string(),
+            tokens,
+        })
+    }
+    
+    pub fn get<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.tokens.iter().try_fold(value, |current, token| match current {
+            Value::Object(map) => map.get(token),
+            Value::Array(items) => token.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+    }
+    
+    /// Sets the field, creating intermediate objects as needed.
+    pub fn set(&self, value: &mut Value, new: Value) -> Result<()> {
+        let (last, parents) = self.tokens.split_last().unwrap();
+        let mut current = value;
+        for token in parents {
+            let Value::Object(map) = current else {
+                return Err(anyhow::anyhow!("Cannot set {}: {} is not an object", self.name, token));
+            };
+            current = map.entry(token.clone()).or_insert_with(|| Value::Object(Map::new()));
+        }
+        match current {
+            Value::Object(map) => {
+                map.insert(last.clone(), new);
+                Ok(())
+            }
+            _ => Err(anyhow::anyhow!("Cannot set {}: the record is not a JSON object", self.name)),
+        }
+    }
+}
+
+/// Settings for `--jsonl-in`, `--field` and `--merge-into`.
+pub struct JsonInput {
+    fields: Vec<FieldPath>,
+    merge_into: Option<FieldPath>,
+}
+
+impl JsonInput {
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if !config.jsonl_in && config.merge_into.is_none() {
+            return Ok(None);
+        }
+        
+        let fields = config.fields
+            .iter()
+            .map(|field| FieldPath::parse(field))
+            .collect::<Result<Vec<_>>>()?;
+        let merge_into = config.merge_into.as_deref().map(FieldPath::parse).transpose()?;
+        Ok(Some(Self { fields, merge_into }))
+    }
+    
+    /// The text the model sees for one record: the selected field(s), or the whole object.
+    ///
+    /// Records that aren't valid JSON are passed through unchanged.
+    pub fn select(&self, record: &str) -> String {
+        match serde_json::from_str::<Value>(record) {
+            Ok(value) => self.select_value(&value),
+            Err(_) => record.to_string(),
+        }
+    }
+    
+    pub fn select_value(&self, value: &Value) -> String {
+        match self.fields.as_slice() {
+            [] => value.to_string(),
+            [field] => field.get(value).map(field_text).unwrap_or_default(),
+            fields => fields
+                .iter()
+                .filter_map(|field| field.get(value).map(|v| format!("{}: {}", field.name, field_text(v))))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+    
+    /// Writes an answer into the record under `--merge-into` and returns the new JSON line.
+    pub fn merge(&self, mut value: Value, answer: &str) -> Result<String> {
+        if let Some(target) = &self.merge_into {
+            target.set(&mut value, Value::String(answer.trim().to_string()))?;
+        }
+        Ok(value.to_string())
+    }
+}
+
+/// Strings are shown without quotes; everything else as compact JSON.
+fn field_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,71 @@
+//! Send text to an Ollama server the way the `inlama` CLI does.
+//!
+//! [`Inlama`] answers whole inputs, either at once or as a stream of chunks,
+//! and [`StreamSession`] batches lines into one ongoing conversation like
+//! `inlama --stream`. Both apply the configured preprocessing and redaction
+//! and use the same server pool and failover as the CLI.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let inlama = inlama::Inlama::builder()
+//!     .model("llama3.2")
+//!     .prompt("Classify the severity of this log line")
+//!     .build()?;
+//! let answer = inlama.complete("disk full on /var").await?;
+//! println!("{}", answer);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The crate root exports only this client API. The modules the CLI is built
+//! from are hidden from the documentation; some stay reachable for the binary
+//! and the tests, but they are not a stable interface.
+
+mod api;
+#[doc(hidden)]
+pub mod app;
+#[doc(hidden)]
+pub mod backpressure;
+mod batching;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod cassette;
+mod cli;
+#[doc(hidden)]
+pub mod codeblocks;
+#[doc(hidden)]
+pub mod config;
+mod conversation;
+#[doc(hidden)]
+pub mod decode;
+mod dedup;
+#[doc(hidden)]
+pub mod endpoints;
+mod framing;
+#[doc(hidden)]
+pub mod handler;
+mod jsonl;
+#[doc(hidden)]
+pub mod limits;
+#[doc(hidden)]
+pub mod markdown;
+#[doc(hidden)]
+pub mod output;
+mod preprocess;
+#[doc(hidden)]
+pub mod redact;
+#[doc(hidden)]
+pub mod requests;
+#[doc(hidden)]
+pub mod signals;
+mod stats;
+mod streams;
+#[doc(hidden)]
+pub mod table;
+mod thinking;
+mod tokens;
+
+pub use api::{Inlama, InlamaBuilder, StreamSession};
+pub use config::Config;
+pub use requests::Chunk;
@@ -0,0 +1,305 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::config::Config;
+use crate::tokens::estimate_tokens;
+
+/// What to keep when the input is larger than `max_input_bytes` / `max_input_tokens`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TruncateStrategy {
+    /// Stop with an error
+    Error,
+    /// Keep the beginning
+    Head,
+    /// Keep the end
+    Tail,
+    /// Keep the beginning and the end, with a marker where input was left out
+    #[value(name = "head+tail")]
+    #[serde(rename = "head+tail")]
+    HeadTail,
+    /// Keep evenly spaced records from the whole input
+    Sample,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Size {
+    bytes: usize,
+    tokens: usize,
+}
+
+impl Size {
+    fn of(record: &str) -> Self {
+        Self {
+            bytes: record.len() + 1,
+            tokens: estimate_tokens(record),
+        }
+    }
+    
+    fn add(&mut self, other: Size) {
+        self.bytes += other.bytes;
+        self.tokens += other.tokens;
+    }
+    
+    fn sub(&mut self, other: Size) {
+        self.bytes -= other.bytes;
+        self.tokens -= other.tokens;
+    }
+}
+
+/// Caps how much input is buffered in oneshot mode.
+///
+/// Only what fits the budget is ever held in memory: `tail` and `head+tail`
+/// keep a ring of the latest records, and `sample` thins out what it has
+/// kept whenever the budget fills up. A single record that is over the
+/// budget on its own is cut short rather than leaving nothing to send.
+pub struct InputLimit {
+    max_bytes: Option<usize>,
+    max_tokens: Option<usize>,
+    strategy: TruncateStrategy,
+    head: Vec<String>,
+    head_size: Size,
+    head_full: bool,
+    tail: VecDeque<(u64, String)>,
+    tail_size: Size,
+    stride: u64,
+    seen: u64,
+    seen_size: Size,
+    cut: u64,
+}
+
+impl InputLimit {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_bytes: config.max_input_bytes,
+            max_tokens: config.max_input_tokens,
+            strategy: config.truncate,
+            head: Vec::new(),
+            head_size: Size::default(),
+            head_full: false,
+            tail: VecDeque::new(),
+            tail_size: Size::default(),
+            stride: 1,
+            seen: 0,
+            seen_size: Size::default(),
+            cut: 0,
+        }
+    }
+    
+    fn is_limited(&self) -> bool {
+        self.max_bytes.is_some() || self.max_tokens.is_some()
+    }
+    
+    /// Whether `size` fits in the given share (1 = all, 2 = half) of the budget.
+    fn fits(&self, size: Size, share: usize) -> bool {
+        self.max_bytes.is_none_or(|max| size.bytes <= max / share)
+            && self.max_tokens.is_none_or(|max| size.tokens <= max / share)
+    }
+    
+    pub fn push(&mut self, record: String) -> Result<()> {
+        let size = Size::of(&record);
+        let index = self.seen;
+        self.seen += 1;
+        self.seen_size.add(size);
+        
+        if !self.is_limited() {
+            self.head.push(record);
+            return Ok(());
+        }
+        
+        match self.strategy {
+            TruncateStrategy::Error => {
+                let mut total = self.head_size;
+                total.add(size);
+                if !self.fits(total, 1) {
+                    return Err(anyhow::anyhow!(
+                        "Input is larger than {} (use --truncate head, tail, head+tail or sample to keep part of it)",
+                        self.describe_limit()
+                    ));
+                }
+                self.push_head(record, size);
+            }
+            TruncateStrategy::Head => {
+                let mut total = self.head_size;
+                total.add(size);
+                if !self.head_full && self.fits(total, 1) {
+                    self.push_head(record, size);
+                } else if !self.head_full {
+                    self.head_full = true;
+                    if self.head.is_empty() {
+                        let record = self.cut(record, 1, false);
+                        let size = Size::of(&record);
+                        self.push_head(record, size);
+                    }
+                }
+            }
+            TruncateStrategy::Tail => self.push_tail(index, record, 1),
+            TruncateStrategy::HeadTail => {
+                let mut total = self.head_size;
+                total.add(size);
+                if !self.head_full && self.fits(total, 2) {
+                    self.push_head(record, size);
+                } else {
+                    self.head_full = true;
+                    self.push_tail(index, record, 2);
+                }
+            }
+            TruncateStrategy::Sample => {
+                if !index.is_multiple_of(self.stride) {
+                    return Ok(());
+                }
+                // Cut first, so the loop below can always get back under the budget
+                let record = self.cut(record, 1, false);
+                self.tail_size.add(Size::of(&record));
+                self.tail.push_back((index, record));
+                // Halve the sampling rate until what was kept fits again
+                while !self.fits(self.tail_size, 1) && self.tail.len() > 1 {
+                    self.stride *= 2;
+                    let stride = self.stride;
+                    self.tail.retain(|(i, _)| i.is_multiple_of(stride));
+                    self.tail_size = Size::default();
+                    for (_, kept) in &self.tail {
+                        self.tail_size.add(Size::of(kept));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    fn push_head(&mut self, record: String, size: Size) {
+        self.head_size.add(size);
+        self.head.push(record);
+    }
+    
+    fn push_tail(&mut self, index: u64, record: String, share: usize) {
+        let record = self.cut(record, share, true);
+        self.tail_size.add(Size::of(&record));
+        self.tail.push_back((index, record));
+        while !self.fits(self.tail_size, share) {
+            let Some((_, dropped)) = self.tail.pop_front() else {
+                break;
+            };
+            self.tail_size.sub(Size::of(&dropped));
+        }
+    }
+    
+    /// Shortens a record that doesn't fit the share of the budget on its own,
+    /// keeping its start or its end.
+    fn cut(&mut self, record: String, share: usize, keep_end: bool) -> String {
+        if self.fits(Size::of(&record), share) {
+            return record;
+        }
+        self.cut += 1;
+        // Size::of counts a newline after the record, and about four characters per token
+        let max_bytes = self.max_bytes.map_or(usize::MAX, |max| (max / share).saturating_sub(1));
+        let max_chars = self.max_tokens.map_or(usize::MAX, |max| (max / share).saturating_mul(4));
+        let mut bytes = 0;
+        let mut chars = 0;
+        let mut fits = |c: char| {
+            bytes += c.len_utf8();
+            chars += 1;
+            bytes <= max_bytes && chars <= max_chars
+        };
+        
+        if keep_end {
+            let start = record
+                .char_indices()
+                .rev()
+                .take_while(|&(_, c)| fits(c))
+                .last()
+                .map_or(record.len(), |(i, _)| i);
+            record[start..].to_string()
+        } else {
+            let end = record
+                .char_indices()
+                .take_while(|&(_, c)| fits(c))
+                .last()
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            record[..end].to_string()
+        }
+    }
+    
+    /// Drops more of the kept records, oldest tail records first, until the
+    /// `head+tail` marker fits in the budget alongside them.
+    fn make_room_for_marker(&mut self) {
+        loop {
+            let dropped = self.seen - (self.head.len() + self.tail.len()) as u64;
+            if dropped == 0 {
+                return;
+            }
+            let mut total = self.head_size;
+            total.add(self.tail_size);
+            total.add(Size::of(&marker(dropped)));
+            if self.fits(total, 1) {
+                return;
+            }
+            
+            if let Some((_, record)) = self.tail.pop_front() {
+                self.tail_size.sub(Size::of(&record));
+            } else if let Some(record) = self.head.pop() {
+                self.head_size.sub(Size::of(&record));
+            } else {
+                return;
+            }
+        }
+    }
+    
+    fn describe_limit(&self) -> String {
+        let mut limits = Vec::new();
+        if let Some(bytes) = self.max_bytes {
+            limits.push(format!("{} bytes", bytes));
+        }
+        if let Some(tokens) = self.max_tokens {
+            limits.push(format!("about {} tokens", tokens));
+        }
+        limits.join(" / ")
+    }
+    
+    /// Returns the kept records, reporting on stderr what was left out.
+    pub fn finish(mut self) -> Vec<String> {
+        if self.strategy == TruncateStrategy::HeadTail {
+            self.make_room_for_marker();
+        }
+        let kept_count = self.head.len() + self.tail.len();
+        let mut kept_size = self.head_size;
+        kept_size.add(self.tail_size);
+        let dropped = self.seen - kept_count as u64;
+        if dropped == 0 && self.cut == 0 {
+            let mut records = self.head;
+            records.extend(self.tail.into_iter().map(|(_, record)| record));
+            return records;
+        }
+        
+        let mut dropped_size = self.seen_size;
+        dropped_size.sub(kept_size);
+        let cut = if self.cut > 0 {
+            format!(", cut {} records short", self.cut)
+        } else {
+            String::new()
+        };
+        eprintln!(
+            "Input is larger than {}: kept {} of {} records, dropped {} records ({} bytes){}",
+            self.describe_limit(),
+            kept_count,
+            self.seen,
+            dropped,
+            dropped_size.bytes,
+            cut
+        );
+        
+        let mut records = self.head;
+        if self.strategy == TruncateStrategy::HeadTail && dropped > 0 {
+            records.push(marker(dropped));
+        }
+        records.extend(self.tail.into_iter().map(|(_, record)| record));
+        records
+    }
+}
+
+/// Stands in for the records `head+tail` left out.
+fn marker(dropped: u64) -> String {
+    format!("[... {} records omitted ...]", dropped)
+}
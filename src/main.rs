@@ -1,10 +1,9 @@
-mod cli;
-mod config;
-mod handler;
-mod requests;
-mod streams;
-
 use anyhow::Result;
+use std::sync::Arc;
+
+use inlama::app::AppContext;
+use inlama::output::Destination;
+use inlama::{config, handler, signals};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -12,12 +11,26 @@ async fn main() -> Result<()> {
     
     // Parse command line arguments
     let config = config::load_config()?;
+    let ctx = Arc::new(AppContext::new(config)?);
+    signals::listen(Arc::clone(&ctx));
     
     // Determine whether to use oneshot or streaming mode
-    if config.stream {
-        handler::stream_handler(&config).await?;
+    if ctx.config.table.is_some() {
+        handler::table_handler(Arc::clone(&ctx), std::io::stdin(), Destination::Stdout).await?;
+    } else if ctx.config.merge_into.is_some() {
+        handler::enrich_handler(Arc::clone(&ctx), tokio::io::stdin(), Destination::Stdout).await?;
+    } else if ctx.config.stream {
+        handler::stream_handler(Arc::clone(&ctx), tokio::io::stdin(), Destination::Stdout).await?;
     } else {
-        handler::oneshot_handler(&config).await?;
+        handler::oneshot_handler(Arc::clone(&ctx), tokio::io::stdin(), Destination::Stdout).await?;
+        if ctx.cancellation.was_interrupted() {
+            std::process::exit(signals::INTERRUPTED_EXIT_CODE);
+        }
+    }
+    
+    if ctx.cancellation.is_shutting_down() {
+        // Exit now rather than letting the runtime wait for the pending stdin read
+        std::process::exit(0);
     }
     
     Ok(())
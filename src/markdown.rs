@@ -0,0 +1,307 @@
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const CYAN: &str = "\x1b[36m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+
+struct CodeBlock {
+    fence: String,
+}
+
+/// Incremental markdown to ANSI renderer for terminal output.
+///
+/// Chunks are rendered as soon as a line is complete, so the answer appears
+/// line by line instead of after the whole response has arrived. Tables are
+/// the exception: their rows are held back until the table ends so the
+/// columns can be aligned.
+pub struct MarkdownRenderer {
+    pending: String,
+    code: Option<CodeBlock>,
+    table: Vec<String>,
+    highlighter: Option<HighlightLines<'static>>,
+}
+
+impl MarkdownRenderer {
+    pub fn new() -> Self {
+        Self {
+            pending: String::new(),
+            code: None,
+            table: Vec::new(),
+            highlighter: None,
+        }
+    }
+    
+    /// Feeds a streamed chunk and returns whatever can be rendered so far.
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+        let mut out = String::new();
+        while let Some(pos) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=pos).collect();
+            self.render_line(line.trim_end_matches(['\n', '\r']), &mut out);
+        }
+        out
+    }
+    
+    /// Renders anything still buffered at the end of a response.
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.render_line(&line, &mut out);
+        }
+        self.flush_table(&mut out);
+        if self.code.take().is_some() {
+            self.highlighter = None;
+            out.push_str(RESET);
+        }
+        out
+    }
+    
+    fn render_line(&mut self, line: &str, out: &mut String) {
+        let trimmed = line.trim_start();
+        
+        if let Some(code) = &self.code {
+            if trimmed.starts_with(&code.fence) && trimmed.trim_end() == code.fence {
+                out.push_str(DIM);
+                out.push_str(&"─".repeat(3));
+                out.push_str(RESET);
+                out.push('\n');
+                self.code = None;
+                self.highlighter = None;
+            } else {
+                self.render_code_line(line, out);
+            }
+            return;
+        }
+        
+        if trimmed.starts_with('|') {
+            self.table.push(trimmed.to_string());
+            return;
+        }
+        self.flush_table(out);
+        
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let fence: String = trimmed.chars().take_while(|c| *c == '`' || *c == '~').collect();
+            let language = trimmed[fence.len()..].trim().to_string();
+            self.start_code_block(&language);
+            out.push_str(DIM);
+            out.push_str(format!("─── {}", language).trim_end());
+            out.push_str(RESET);
+            out.push('\n');
+            self.code = Some(CodeBlock { fence });
+            return;
+        }
+        
+        let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+            let style = if hashes == 1 { format!("{}{}{}", BOLD, UNDERLINE, MAGENTA) } else { format!("{}{}", BOLD, MAGENTA) };
+            out.push_str(&style);
+            out.push_str(&render_inline(trimmed[hashes..].trim(), &style));
+            out.push_str(RESET);
+            out.push('\n');
+            return;
+        }
+        
+        if is_rule(trimmed) {
+            out.push_str(DIM);
+            out.push_str(&"─".repeat(40));
+            out.push_str(RESET);
+            out.push('\n');
+            return;
+        }
+        
+        let indent = &line[..line.len() - trimmed.len()];
+        if let Some(rest) = trimmed.strip_prefix("> ") {
+            out.push_str(&format!("{}{}│ {}{}", indent, DIM, render_inline(rest, DIM), RESET));
+        } else if let Some(rest) = ["- ", "* ", "+ "].iter().find_map(|p| trimmed.strip_prefix(p)) {
+            out.push_str(&format!("{}{}•{} {}", indent, YELLOW, RESET, render_inline(rest, "")));
+        } else if let Some((number, rest)) = ordered_item(trimmed) {
+            out.push_str(&format!("{}{}{}.{} {}", indent, YELLOW, number, RESET, render_inline(rest, "")));
+        } else {
+            out.push_str(&render_inline(line, ""));
+        }
+        out.push('\n');
+    }
+    
+    fn start_code_block(&mut self, language: &str) {
+        self.highlighter = syntaxes()
+            .find_syntax_by_token(language)
+            .map(|syntax| HighlightLines::new(syntax, theme()));
+    }
+    
+    fn render_code_line(&mut self, line: &str, out: &mut String) {
+        let line_with_newline = format!("{}\n", line);
+        let highlighted = self.highlighter
+            .as_mut()
+            .and_then(|h| h.highlight_line(&line_with_newline, syntaxes()).ok());
+        match highlighted {
+            Some(ranges) => {
+                out.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+                out.push_str(RESET);
+            }
+            None => {
+                out.push_str(CYAN);
+                out.push_str(line);
+                out.push_str(RESET);
+                out.push('\n');
+            }
+        }
+    }
+    
+    fn flush_table(&mut self, out: &mut String) {
+        if self.table.is_empty() {
+            return;
+        }
+        
+        let rows: Vec<Vec<String>> = self.table
+            .drain(..)
+            .map(|row| {
+                row.trim()
+                    .trim_start_matches('|')
+                    .trim_end_matches('|')
+                    .split('|')
+                    .map(|cell| cell.trim().to_string())
+                    .collect()
+            })
+            .collect();
+        
+        let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in rows.iter().filter(|r| !is_separator_row(r)) {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(visible_width(cell));
+            }
+        }
+        
+        for (index, row) in rows.iter().enumerate() {
+            if is_separator_row(row) {
+                let line: Vec<String> = widths.iter().map(|w| "─".repeat(*w)).collect();
+                out.push_str(&format!("{}{}{}\n", DIM, line.join("─┼─"), RESET));
+                continue;
+            }
+            let style = if index == 0 { BOLD } else { "" };
+            let cells: Vec<String> = (0..columns)
+                .map(|i| {
+                    let cell = row.get(i).map(String::as_str).unwrap_or("");
+                    let padding = " ".repeat(widths[i] - visible_width(cell));
+                    format!("{}{}{}{}", style, render_inline(cell, style), RESET, padding)
+                })
+                .collect();
+            out.push_str(&cells.join(&format!(" {}│{} ", DIM, RESET)));
+            out.push('\n');
+        }
+    }
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Loading the syntax definitions is slow, so it only happens on the first code block
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    THEME.get_or_init(|| {
+        ThemeSet::load_defaults()
+            .themes
+            .remove("base16-ocean.dark")
+            .unwrap_or_default()
+    })
+}
+
+fn is_rule(line: &str) -> bool {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.len() >= 3
+        && ["-", "*", "_"].iter().any(|c| compact.chars().all(|x| x.to_string() == *c))
+}
+
+fn ordered_item(line: &str) -> Option<(&str, &str)> {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..].strip_prefix(". ").map(|rest| (&line[..digits], rest))
+}
+
+fn is_separator_row(row: &[String]) -> bool {
+    !row.is_empty() && row.iter().all(|c| !c.is_empty() && c.chars().all(|x| matches!(x, '-' | ':')))
+}
+
+/// Width of a cell as displayed, ignoring inline markdown markers.
+fn visible_width(cell: &str) -> usize {
+    cell.chars().filter(|c| !matches!(c, '*' | '`')).count()
+}
+
+/// Renders `**bold**`, `*italic*`, `_italic_` and `` `code` `` spans.
+///
+/// `base` is the style of the surrounding text, restored after each span.
+fn render_inline(text: &str, base: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let mut i = 0;
+    
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '`' {
+            if let Some(end) = chars[i + 1..].iter().position(|x| *x == '`') {
+                let code: String = chars[i + 1..i + 1 + end].iter().collect();
+                out.push_str(&format!("{}{}{}{}", RESET, CYAN, code, RESET));
+                out.push_str(base);
+                if bold { out.push_str(BOLD); }
+                if italic { out.push_str(ITALIC); }
+                i += end + 2;
+                continue;
+            }
+        } else if (c == '*' || c == '_') && chars.get(i + 1) == Some(&c) {
+            bold = !bold;
+            out.push_str(if bold { BOLD } else { RESET });
+            if !bold {
+                out.push_str(base);
+                if italic { out.push_str(ITALIC); }
+            }
+            i += 2;
+            continue;
+        } else if c == '*' || (c == '_' && !is_word_char(chars.get(i.wrapping_sub(1)))) || (c == '_' && italic) {
+            let opens = !italic && chars.get(i + 1).is_some_and(|x| !x.is_whitespace());
+            if italic || opens {
+                italic = !italic;
+                out.push_str(if italic { ITALIC } else { RESET });
+                if !italic {
+                    out.push_str(base);
+                    if bold { out.push_str(BOLD); }
+                }
+                i += 1;
+                continue;
+            }
+        }
+        out.push(c);
+        i += 1;
+    }
+    
+    if bold || italic {
+        out.push_str(RESET);
+        out.push_str(base);
+    }
+    out
+}
+
+fn is_word_char(c: Option<&char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric())
+}
@@ -0,0 +1,341 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::codeblocks::CodeExtraction;
+use crate::config::Config;
+use crate::markdown::MarkdownRenderer;
+use crate::redact::{Redactor, Unredactor};
+use crate::requests::Generation;
+use crate::stats::GenerationStats;
+
+/// How answers are written to stdout (and the tee file).
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Stream the raw answer text
+    Text,
+    /// One JSON object per request, one per line
+    Jsonl,
+    /// One pretty-printed JSON object per request
+    Json,
+}
+
+/// Metadata envelope emitted for each request in `jsonl` and `json` modes.
+#[derive(Debug, Serialize)]
+struct Envelope<'a> {
+    input_hash: &'a str,
+    batch_index: u64,
+    model: &'a str,
+    response: &'a str,
+    done_reason: Option<&'a str>,
+    stats: Option<&'a GenerationStats>,
+    started_at: String,
+    finished_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Where a handler writes its answers.
+pub enum Destination {
+    Stdout,
+    /// Any writer, e.g. a buffer in tests; Markdown is never rendered here
+    Writer(Box<dyn Write + Send>),
+}
+
+/// Destination for model output shared by both handlers.
+///
+/// Text mode streams chunks as they arrive; the structured modes collect the
+/// answer and emit a single envelope once the request has finished. In a dry
+/// run the answers are request reports, printed as they are with no other output.
+pub struct OutputSink {
+    format: OutputFormat,
+    model: String,
+    stdout: Box<dyn Write + Send>,
+    tee: Option<File>,
+    renderer: Option<MarkdownRenderer>,
+    extraction: Option<CodeExtraction>,
+    unredactor: Option<Unredactor>,
+    thinking_unredactor: Option<Unredactor>,
+    show_thinking: bool,
+    thinking_open: bool,
+    stream: bool,
+    dry_run: bool,
+    batch_index: u64,
+    input_hash: String,
+    started_at: DateTime<Utc>,
+    response: String,
+}
+
+impl OutputSink {
+    pub fn new(config: &Config, redactor: Option<Arc<Redactor>>, destination: Destination) -> Result<Self> {
+        let tee = match &config.tee {
+            // A dry run answers nothing, so it must not truncate the file either
+            Some(_) if config.dry_run => None,
+            Some(path) => {
+                let expanded = shellexpand::tilde(path);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(Path::new(expanded.as_ref()))
+                    .map_err(|e| anyhow::anyhow!("Error opening output file {}: {}", path, e))?;
+                Some(file)
+            }
+            None => None,
+        };
+        
+        // Markdown is only rendered for a person looking at a terminal
+        let (stdout, terminal): (Box<dyn Write + Send>, bool) = match destination {
+            Destination::Stdout => {
+                let stdout = io::stdout();
+                let terminal = stdout.is_terminal();
+                (Box::new(stdout), terminal)
+            }
+            Destination::Writer(writer) => (writer, false),
+        };
+        let extraction = CodeExtraction::from_config(config).filter(|_| !config.dry_run);
+        let extracting = extraction.as_ref().is_some_and(|e| e.extract);
+        let render = config.output == OutputFormat::Text
+            && !config.dry_run
+            && !extracting
+            && !config.raw
+            && terminal
+            && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+        
+        // A dry run's report shows the request as sent, placeholders and all
+        let redactor = redactor.filter(|_| config.unredact && !config.dry_run);
+        Ok(Self {
+            format: config.output,
+            model: config.model.clone(),
+            stdout,
+            tee,
+            renderer: render.then(MarkdownRenderer::new),
+            extraction,
+            unredactor: redactor.clone().map(Unredactor::new),
+            thinking_unredactor: redactor.map(Unredactor::new),
+            show_thinking: config.show_thinking,
+            thinking_open: false,
+            stream: config.stream,
+            dry_run: config.dry_run,
+            batch_index: 0,
+            input_hash: String::new(),
+            started_at: Utc::now(),
+            response: String::new(),
+        })
+    }
+    
+    /// Starts a new response for the given request body.
+    pub fn begin(&mut self, input: &str) {
+        self.batch_index += 1;
+        self.input_hash = hash_input(input);
+        self.started_at = Utc::now();
+        self.response.clear();
+    }
+    
+    pub fn write_chunk(&mut self, chunk: &str) -> Result<()> {
+        match &mut self.unredactor {
+            Some(unredactor) => {
+                let restored = unredactor.push(chunk);
+                self.write_text(&restored)
+            }
+            None => self.write_text(chunk),
+        }
+    }
+    
+    fn write_text(&mut self, chunk: &str) -> Result<()> {
+        self.response.push_str(chunk);
+        if self.dry_run {
+            return Ok(());
+        }
+        // Extracted code can only be emitted once the whole answer is known
+        let buffered = self.extraction.as_ref().is_some_and(|e| e.extract);
+        if self.format == OutputFormat::Text && !buffered {
+            match &mut self.renderer {
+                Some(renderer) => {
+                    let rendered = renderer.push(chunk);
+                    self.write_stdout(rendered.as_bytes())?;
+                    self.write_tee(chunk.as_bytes())?;
+                }
+                None => self.emit(chunk.as_bytes())?,
+            }
+        }
+        Ok(())
+    }
+    
+    /// Writes a complete output line, bypassing rendering and envelopes (used by `--merge-into`).
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.emit(format!("{}\n", line).as_bytes())
+    }
+    
+    /// Prints a dry run's report of a request, for handlers that collect answers themselves.
+    pub fn write_report(&mut self, report: &str) -> Result<()> {
+        self.write_stdout(format!("{}\n", report.trim_end()).as_bytes())
+    }
+    
+    /// Shows model reasoning on stderr (dimmed on a terminal) when `--show-thinking` is set.
+    pub fn write_thinking(&mut self, text: &str) -> Result<()> {
+        if !self.show_thinking {
+            return Ok(());
+        }
+        // Reasoning often repeats the input, placeholders included
+        let text = match &mut self.thinking_unredactor {
+            Some(unredactor) => unredactor.push(text),
+            None => text.to_string(),
+        };
+        self.print_thinking(&text)
+    }
+    
+    fn print_thinking(&mut self, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        
+        let mut stderr = io::stderr();
+        if stderr.is_terminal() {
+            write!(stderr, "\x1b[2m{}\x1b[0m", text)?;
+        } else {
+            stderr.write_all(text.as_bytes())?;
+        }
+        stderr.flush()?;
+        self.thinking_open = !text.ends_with('\n');
+        Ok(())
+    }
+    
+    /// Completes the current response, emitting the envelope in structured modes.
+    pub fn finish(&mut self, result: Result<&Generation, &anyhow::Error>) -> Result<()> {
+        if self.dry_run {
+            let report = std::mem::take(&mut self.response);
+            return self.write_report(&report);
+        }
+        if let Some(rest) = self.thinking_unredactor.as_mut().map(Unredactor::finish) {
+            self.print_thinking(&rest)?;
+        }
+        if self.thinking_open {
+            eprintln!();
+            self.thinking_open = false;
+        }
+        
+        // Release a trailing "<..." held back in case it was a split placeholder
+        if let Some(unredactor) = &mut self.unredactor {
+            let rest = unredactor.finish();
+            self.write_text(&rest)?;
+        }
+        
+        if result.is_ok() {
+            self.extract_code()?;
+        }
+        
+        match self.format {
+            OutputFormat::Text => {
+                if let Some(renderer) = &mut self.renderer {
+                    let rendered = renderer.finish();
+                    self.write_stdout(rendered.as_bytes())?;
+                }
+                
+                // Make sure we have a final newline
+                if !self.response.is_empty() && !self.response.ends_with('\n') {
+                    if self.renderer.is_none() {
+                        self.write_stdout(b"\n")?;
+                    }
+                    self.write_tee(b"\n")?;
+                }
+            }
+            OutputFormat::Jsonl | OutputFormat::Json => {
+                let generation = result.ok();
+                let envelope = Envelope {
+                    input_hash: &self.input_hash,
+                    batch_index: self.batch_index,
+                    model: generation.map_or(self.model.as_str(), |g| g.model.as_str()),
+                    response: &self.response,
+                    done_reason: generation.and_then(|g| g.done_reason.as_deref()),
+                    stats: generation.map(|g| &g.stats),
+                    started_at: timestamp(self.started_at),
+                    finished_at: timestamp(Utc::now()),
+                    error: result.err().map(|e| e.to_string()),
+                };
+                let mut line = if self.format == OutputFormat::Json {
+                    serde_json::to_string_pretty(&envelope)?
+                } else {
+                    serde_json::to_string(&envelope)?
+                };
+                line.push('\n');
+                self.emit(line.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Applies `--extract-code` / `--write-blocks` to the finished answer.
+    fn extract_code(&mut self) -> Result<()> {
+        let Some(extraction) = &self.extraction else {
+            return Ok(());
+        };
+        
+        let blocks = extraction.select(&self.response);
+        for path in extraction.write_blocks(&blocks)? {
+            eprintln!("Wrote {}", path.display());
+        }
+        
+        if !extraction.extract {
+            return Ok(());
+        }
+        
+        if blocks.is_empty() {
+            // A batch without code shouldn't end a long-running stream
+            if self.stream {
+                eprintln!("No matching code block found in response {}", self.batch_index);
+                self.response.clear();
+                return Ok(());
+            }
+            return Err(anyhow::anyhow!("No matching code block found in the response"));
+        }
+        
+        let code: Vec<&str> = blocks.iter().map(|b| b.content.as_str()).collect();
+        self.response = code.join("\n");
+        if self.format == OutputFormat::Text {
+            let code = std::mem::take(&mut self.response);
+            self.emit(code.as_bytes())?;
+            self.response = code;
+        }
+        Ok(())
+    }
+    
+    fn emit(&mut self, bytes: &[u8]) -> Result<()> {
+        self.write_stdout(bytes)?;
+        self.write_tee(bytes)
+    }
+    
+    fn write_stdout(&mut self, bytes: &[u8]) -> Result<()> {
+        if !bytes.is_empty() {
+            self.stdout.write_all(bytes)?;
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+    
+    fn write_tee(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(tee) = &mut self.tee {
+            tee.write_all(bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hex SHA-256 of a request body, used to correlate answers with their input.
+pub fn hash_input(input: &str) -> String {
+    format!("{:x}", Sha256::digest(input.as_bytes()))
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
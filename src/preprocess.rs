@@ -0,0 +1,77 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::dedup;
+use crate::jsonl::JsonInput;
+use crate::redact::Redactor;
+
+/// Transformations applied to input lines before they are sent to the model.
+///
+/// Sits between the stdin readers in `streams` and the handlers, and runs on
+/// the whole input in oneshot mode or on each batch in stream mode.
+pub struct Preprocessor {
+    collapse: bool,
+    collapse_min_count: usize,
+    redactor: Option<Arc<Redactor>>,
+    json: Option<JsonInput>,
+    debug: bool,
+}
+
+impl Preprocessor {
+    pub fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            collapse: config.collapse,
+            collapse_min_count: config.collapse_min_count,
+            redactor: Redactor::from_config(config)?.map(Arc::new),
+            json: JsonInput::from_config(config)?,
+            debug: config.debug,
+        })
+    }
+    
+    pub fn apply(&self, lines: Vec<String>) -> Vec<String> {
+        // With --jsonl-in the model only sees the selected fields of each record
+        let lines = match &self.json {
+            Some(json) => lines
+                .iter()
+                .map(|record| json.select(record))
+                .filter(|selected| !selected.trim().is_empty())
+                .collect(),
+            None => lines,
+        };
+        
+        if !self.collapse {
+            return lines;
+        }
+        
+        let before = lines.len();
+        let collapsed = dedup::collapse(lines, self.collapse_min_count);
+        if self.debug {
+            eprintln!("Collapsed {} lines into {}", before, collapsed.len());
+        }
+        collapsed
+    }
+    
+    /// Replaces secrets in a request body just before it leaves the machine.
+    pub fn redact(&self, body: &str) -> String {
+        let Some(redactor) = &self.redactor else {
+            return body.to_string();
+        };
+        
+        let redacted = redactor.redact(body);
+        if self.debug {
+            eprintln!("Redaction placeholders in use: {}", redactor.count());
+        }
+        redacted
+    }
+    
+    /// JSON record handling for `--jsonl-in` and `--merge-into`.
+    pub fn json_input(&self) -> Option<&JsonInput> {
+        self.json.as_ref()
+    }
+    
+    /// The redactor whose placeholders `--unredact` restores in the output.
+    pub fn redactor(&self) -> Option<Arc<Redactor>> {
+        self.redactor.clone()
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Result;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::config::Config;
+
+/// A user-defined redaction rule from `[[redact_rules]]` or `--redact-rule NAME=REGEX`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RedactRule {
+    pub name: String,
+    pub pattern: String,
+}
+
+impl RedactRule {
+    pub fn parse(rule: &str) -> Result<Self> {
+        let (name, pattern) = rule
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid redact rule {:?}, expected NAME=REGEX", rule))?;
+        Ok(Self {
+            name: name.trim().to_string(),
+            pattern: pattern.to_string(),
+        })
+    }
+    
+    /// The placeholder label for this rule, which must match what `--unredact` recognises.
+    pub fn label(&self) -> Result<String> {
+        let label = self.name.to_uppercase();
+        let valid = !label.is_empty()
+            && label.len() <= MAX_LABEL_LEN
+            && label.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "Invalid redact rule name {:?}: use up to {} letters, digits and underscores",
+                self.name,
+                MAX_LABEL_LEN
+            ));
+        }
+        Ok(label)
+    }
+}
+
+struct Detector {
+    label: String,
+    regex: Regex,
+    /// Only the text of this capture group is replaced (e.g. the token after "Bearer ")
+    group: usize,
+    /// Extra check for matches a regex can't validate on its own
+    validate: Option<Validator>,
+}
+
+type Validator = fn(&str) -> bool;
+
+/// Built-in detectors, ordered so the more specific ones run first.
+const BUILTIN: &[(&str, &str, usize, Option<Validator>)] = &[
+    ("PRIVATE_KEY", r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----", 0, None),
+    ("JWT", r"\beyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+", 0, None),
+    ("TOKEN", r"(?i)\bbearer\s+([A-Za-z0-9._~+/=-]{8,})", 1, None),
+    ("AWS_KEY", r"\b(?:AKIA|ASIA|AIDA|AROA)[0-9A-Z]{16}\b", 0, None),
+    ("AWS_SECRET", r#"(?i)aws_secret_access_key["']?\s*[:=]\s*["']?([A-Za-z0-9/+=]{40})"#, 1, None),
+    ("EMAIL", r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b", 0, None),
+    ("CARD", r"\b(?:\d[ -]?){12,18}\d\b", 0, Some(luhn_valid)),
+    ("IP", r"(?i)\b(?:[0-9a-f]{1,4}:){7}[0-9a-f]{1,4}\b|\b(?:[0-9a-f]{1,4}:){1,6}:(?:[0-9a-f]{1,4}(?::[0-9a-f]{1,4}){0,5})?\b", 0, Some(ipv6_valid)),
+    ("IP", r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b", 0, None),
+];
+
+/// Replaces secrets and personal data with stable placeholders such as `<EMAIL_1>`.
+///
+/// The same value always maps to the same placeholder for the whole run, so the
+/// model can still tell repeated values apart, and the mapping never leaves the
+/// machine, which lets answers be un-redacted locally.
+pub struct Redactor {
+    detectors: Vec<Detector>,
+    state: Mutex<Placeholders>,
+}
+
+#[derive(Default)]
+struct Placeholders {
+    by_value: HashMap<(String, String), String>,
+    by_placeholder: HashMap<String, String>,
+    counters: HashMap<String, usize>,
+}
+
+impl Redactor {
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if !config.redact {
+            return Ok(None);
+        }
+        
+        // User rules run first so they can claim matches the built-ins would take
+        let mut detectors = Vec::new();
+        for rule in &config.redact_rules {
+            let label = rule.label()?;
+            let regex = Regex::new(&rule.pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid redact rule {}: {}", rule.name, e))?;
+            detectors.push(Detector {
+                label,
+                regex,
+                group: 0,
+                validate: None,
+            });
+        }
+        for (label, pattern, group, validate) in BUILTIN {
+            detectors.push(Detector {
+                label: label.to_string(),
+                regex: Regex::new(pattern).expect("valid built-in pattern"),
+                group: *group,
+                validate: *validate,
+            });
+        }
+        
+        Ok(Some(Self {
+            detectors,
+            state: Mutex::new(Placeholders::default()),
+        }))
+    }
+    
+    pub fn redact(&self, text: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let mut redacted = text.to_string();
+        
+        for detector in &self.detectors {
+            redacted = detector.regex
+                .replace_all(&redacted, |caps: &Captures| {
+                    let whole = &caps[0];
+                    let Some(secret) = caps.get(detector.group) else {
+                        return whole.to_string();
+                    };
+                    if detector.validate.is_some_and(|valid| !valid(secret.as_str())) {
+                        return whole.to_string();
+                    }
+                    let placeholder = state.placeholder_for(&detector.label, secret.as_str());
+                    let start = secret.start() - caps.get(0).unwrap().start();
+                    format!("{}{}{}", &whole[..start], placeholder, &whole[start + secret.len()..])
+                })
+                .into_owned();
+        }
+        
+        redacted
+    }
+    
+    /// The original value behind a placeholder, if this run produced it.
+    pub fn original(&self, placeholder: &str) -> Option<String> {
+        self.state.lock().unwrap().by_placeholder.get(placeholder).cloned()
+    }
+    
+    pub fn count(&self) -> usize {
+        self.state.lock().unwrap().by_placeholder.len()
+    }
+}
+
+impl Placeholders {
+    fn placeholder_for(&mut self, label: &str, value: &str) -> String {
+        let key = (label.to_string(), value.to_string());
+        if let Some(existing) = self.by_value.get(&key) {
+            return existing.clone();
+        }
+        
+        let counter = self.counters.entry(label.to_string()).or_insert(0);
+        *counter += 1;
+        let placeholder = format!("<{}_{}>", label, counter);
+        self.by_value.insert(key, placeholder.clone());
+        self.by_placeholder.insert(placeholder.clone(), value.to_string());
+        placeholder
+    }
+}
+
+/// Luhn checksum, so order numbers and timestamps aren't mistaken for card numbers.
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// An IPv6 address with at least one digit, so hex-looking words such as `abc::def` or `dead::beef` are left alone.
+fn ipv6_valid(candidate: &str) -> bool {
+    candidate.parse::<Ipv6Addr>().is_ok() && candidate.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Longest placeholder we wait for when one is split across streamed chunks.
+const MAX_PLACEHOLDER_LEN: usize = 64;
+
+/// Longest rule name, leaving room in a placeholder for the brackets and counter.
+const MAX_LABEL_LEN: usize = 48;
+
+/// Restores original values in streamed model output.
+///
+/// A `<` without its closing `>` is held back until the next chunk, so a
+/// placeholder split across chunks is still recognised.
+pub struct Unredactor {
+    redactor: Arc<Redactor>,
+    pending: String,
+}
+
+impl Unredactor {
+    pub fn new(redactor: Arc<Redactor>) -> Self {
+        Self {
+            redactor,
+            pending: String::new(),
+        }
+    }
+    
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+        
+        let hold_from = self.pending
+            .rfind('<')
+            .filter(|&i| !self.pending[i..].contains('>') && self.pending.len() - i < MAX_PLACEHOLDER_LEN);
+        let ready = match hold_from {
+            Some(i) => {
+                let rest = self.pending.split_off(i);
+                std::mem::replace(&mut self.pending, rest)
+            }
+            None => std::mem::take(&mut self.pending),
+        };
+        self.restore(&ready)
+    }
+    
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.restore(&rest)
+    }
+    
+    fn restore(&self, text: &str) -> String {
+        placeholder_pattern()
+            .replace_all(text, |caps: &Captures| {
+                self.redactor.original(&caps[0]).unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+fn placeholder_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"<[A-Z0-9_]+_\d+>").expect("valid placeholder pattern"))
+}
@@ -1,10 +1,19 @@
 use anyhow::Result;
-use futures_util::StreamExt;
-use reqwest::{Client, Response};
+use futures_util::{stream, Stream, StreamExt};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fmt::Display;
+use std::time::Instant;
 use tokio::sync::mpsc;
 
+use crate::app::AppContext;
+use crate::cassette::{Cassette, ReplayMiss};
 use crate::config::Config;
+use crate::stats::GenerationStats;
+use crate::thinking::ThinkFilter;
+use crate::tokens::estimate_tokens;
 
 #[derive(Debug, Serialize)]
 pub struct OllamaRequest<'a> {
@@ -14,6 +23,28 @@ pub struct OllamaRequest<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context: Option<&'a Vec<i32>>,
     pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<KeepAlive<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub think: Option<bool>,
+}
+
+/// Ollama's `keep_alive`: a number of seconds (negative keeps the model loaded) or a duration string.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum KeepAlive<'a> {
+    Seconds(i64),
+    Duration(&'a str),
+}
+
+impl<'a> KeepAlive<'a> {
+    /// Plain integers such as `-1` must be sent as JSON numbers; Ollama rejects them as strings.
+    pub fn parse(value: &'a str) -> Self {
+        match value.trim().parse() {
+            Ok(seconds) => Self::Seconds(seconds),
+            Err(_) => Self::Duration(value),
+        }
+    }
 }
 
 // Updated to match the actual response format
@@ -23,6 +54,8 @@ pub struct OllamaResponse {
     #[serde(rename = "created_at")]
     pub created_at: String,
     pub response: String,
+    #[serde(default)]
+    pub thinking: Option<String>,
     pub done: bool,
     #[serde(default)]
     pub context: Vec<i32>,
@@ -42,17 +75,76 @@ pub struct OllamaResponse {
     pub eval_duration: Option<u64>,
 }
 
+/// A piece of streamed output, with model reasoning kept apart from the answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chunk {
+    Text(String),
+    Thinking(String),
+}
+
+/// What a finished request leaves behind once its text has been streamed out.
+#[derive(Debug, Clone, Default)]
+pub struct Generation {
+    pub context: Vec<i32>,
+    pub model: String,
+    pub created_at: String,
+    pub done_reason: Option<String>,
+    pub stats: GenerationStats,
+}
+
+/// API the requests are made against; part of every request key.
+const BACKEND: &str = "ollama/api/generate";
+
+/// Hex SHA-256 over everything that shapes the answer, used to find recorded and cached responses.
+///
+/// The server URL is left out so stored answers are reused whichever host served them.
+pub fn request_key(request: &OllamaRequest) -> String {
+    let identity = json!({
+        "backend": BACKEND,
+        "model": request.model,
+        "options": {
+            "context": request.context,
+            "think": request.think,
+        },
+        "system": request.system,
+        "prompt": request.prompt,
+    });
+    format!("{:x}", Sha256::digest(identity.to_string().as_bytes()))
+}
+
+/// What `--dry-run` prints for a request: the body as it would be sent, and its estimated size.
+///
+/// The context is already tokenized, so its count is exact.
+pub fn dry_run_report(request: &OllamaRequest) -> Result<String> {
+    let system = estimate_tokens(request.system);
+    let prompt = estimate_tokens(request.prompt);
+    let context = request.context.map_or(0, Vec::len);
+    let report = json!({
+        "request": request,
+        "estimated_tokens": {
+            "system": system,
+            "prompt": prompt,
+            "context": context,
+            "total": system + prompt + context,
+        },
+    });
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaError {
+    error: String,
+}
+
 pub async fn send_request(
     request: OllamaRequest<'_>,
-    config: &Config,
-    tx: mpsc::Sender<String>,
-) -> Result<Vec<i32>> {
-    let client = Client::new();
-    let request_url = format!("{}/api/generate", config.url);
+    ctx: &AppContext,
+    tx: mpsc::Sender<Chunk>,
+) -> Result<Generation> {
+    let config = &ctx.config;
     
     // Only print debug info if debug mode is enabled
     if config.debug {
-        eprintln!("Making request to: {}", request_url);
         eprintln!("Model: {}", request.model);
         eprintln!("System prompt: {}", request.system);
         eprintln!("User prompt: {}", request.prompt);
@@ -62,34 +154,167 @@ pub async fn send_request(
         eprintln!("Request JSON: {}", request_json);
     }
     
-    let response = match client
-        .post(&request_url)
-        .json(&request)
-        .send()
-        .await {
-            Ok(r) => {
+    // Nothing is sent or looked up; the report stands in for the answer
+    if config.dry_run {
+        let _ = tx.send(Chunk::Text(dry_run_report(&request)?)).await;
+        return Ok(Generation::default());
+    }
+    
+    // Recordings and cached answers are looked up before any server is contacted
+    let cache = ctx.cache.as_ref();
+    let key = (config.record.is_some() || config.replay.is_some() || cache.is_some()).then(|| request_key(&request));
+    if let (Some(dir), Some(key)) = (&config.replay, &key) {
+        let cassette = Cassette::new(dir);
+        match cassette.load(key)? {
+            Some(recorded) => {
                 if config.debug {
-                    eprintln!("Request sent successfully, status: {:?}", r.status());
+                    eprintln!("Replaying {} from {}", key, cassette.dir());
                 }
-                r
-            },
-            Err(e) => {
-                eprintln!("Error sending request: {}", e);
-                return Err(anyhow::anyhow!("Error sending request: {}", e));
+                let frames = stream::iter([Ok::<_, std::io::Error>(recorded)]);
+                return process_stream_response(frames, tx, Instant::now(), config.debug).await;
             }
-        };
+            None if config.replay_miss == ReplayMiss::Live => {
+                if config.debug {
+                    eprintln!("No recording for {}, sending the request", key);
+                }
+            }
+            None => {
+                return Err(anyhow::anyhow!(
+                    "No recording of this request in {} (key {}); use --replay-miss live to send it",
+                    cassette.dir(),
+                    key
+                ));
+            }
+        }
+    }
+    let recorder = config.record.as_deref().map(Cassette::new);
+    
+    // A cached answer would leave nothing to record, so recording always asks the server
+    if let (Some(cache), Some(key), None) = (&cache, &key, &recorder) {
+        match cache.lookup(key) {
+            Ok(Some(cached)) => {
+                if config.debug {
+                    eprintln!("Answering {} from the cache in {}", key, cache.dir());
+                }
+                let frames = stream::iter([Ok::<_, std::io::Error>(cached)]);
+                return process_stream_response(frames, tx, Instant::now(), config.debug).await;
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Error reading the response cache: {}", e),
+        }
+    }
     
-    process_stream_response(response, tx, config.debug).await
+    let candidates = ctx.endpoints.candidates(request.model);
+    if candidates.is_empty() {
+        return Err(anyhow::anyhow!("No endpoint is configured to serve model {}", request.model));
+    }
+    
+    // Fail over to the next endpoint as long as nothing has been emitted yet
+    let mut last_error = None;
+    for endpoint in candidates {
+        if !ctx.endpoints.ensure_available(&ctx.client, &endpoint).await {
+            last_error = Some(anyhow::anyhow!("Endpoint {} failed its health check", endpoint.url));
+            continue;
+        }
+        
+        let request_url = format!("{}/api/generate", endpoint.url);
+        if config.debug {
+            eprintln!("Making request to: {}", request_url);
+        }
+        
+        let _in_flight = endpoint.start();
+        let started = Instant::now();
+        let response = match ctx.client
+            .post(&request_url)
+            .json(&request)
+            .send()
+            .await {
+                Ok(r) => {
+                    if config.debug {
+                        eprintln!("Request sent successfully, status: {:?}", r.status());
+                    }
+                    r
+                },
+                Err(e) => {
+                    if config.debug {
+                        eprintln!("Error sending request to {}: {}", endpoint.url, e);
+                    }
+                    ctx.endpoints.mark_failure(&endpoint);
+                    last_error = Some(anyhow::anyhow!("Error sending request: {}", e));
+                    continue;
+                }
+            };
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<OllamaError>(&body)
+                .map(|e| e.error)
+                .unwrap_or(body);
+            if config.debug {
+                eprintln!("Server {} returned {}: {}", endpoint.url, status, message);
+            }
+            let error = anyhow::anyhow!("Server returned {}: {}", status, message);
+            // A bad request or unknown model would fail on every server, and says nothing about this one's health
+            if !status.is_server_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                return Err(error);
+            }
+            ctx.endpoints.mark_failure(&endpoint);
+            last_error = Some(error);
+            continue;
+        }
+        
+        // Keep the raw bytes when storing, so a replay parses exactly what the server sent
+        let keep = recorder.is_some() || cache.is_some();
+        let mut recorded = Vec::new();
+        let frames = response.bytes_stream().inspect(|item| {
+            if let (true, Ok(bytes)) = (keep, item) {
+                recorded.extend_from_slice(bytes);
+            }
+        });
+        let result = process_stream_response(frames, tx, started, config.debug).await;
+        match &result {
+            Ok(_) => ctx.endpoints.mark_success(&endpoint),
+            Err(_) => ctx.endpoints.mark_failure(&endpoint),
+        }
+        
+        // Only complete answers are recorded or cached; the model is set by the final line
+        let complete = result.as_ref().is_ok_and(|generation| !generation.model.is_empty());
+        if let (Some(recorder), Some(key), true) = (&recorder, &key, complete) {
+            match recorder.store(key, &request, &recorded) {
+                Ok(()) if config.debug => eprintln!("Recorded {} in {}", key, recorder.dir()),
+                Ok(()) => {}
+                Err(e) => eprintln!("Error recording response: {}", e),
+            }
+        }
+        if let (Some(cache), Some(key), true) = (&cache, &key, complete) {
+            if let Err(e) = cache.store(key, &recorded) {
+                eprintln!("Error writing the response cache: {}", e);
+            }
+        }
+        return result;
+    }
+    
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No endpoint available")))
 }
 
-async fn process_stream_response(
-    response: Response,
-    tx: mpsc::Sender<String>,
+/// Parses a streamed NDJSON answer, however its bytes are split into frames.
+pub async fn process_stream_response<S, B, E>(
+    stream: S,
+    tx: mpsc::Sender<Chunk>,
+    started: Instant,
     debug: bool,
-) -> Result<Vec<i32>> {
-    let mut stream = response.bytes_stream();
+) -> Result<Generation>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Display,
+{
+    tokio::pin!(stream);
     let mut buffer = Vec::new();
-    let mut context = Vec::new();
+    let mut generation = Generation::default();
+    let mut first_token = None;
+    let mut think_filter = ThinkFilter::new();
     let mut response_count = 0;
     
     if debug {
@@ -100,7 +325,7 @@ async fn process_stream_response(
         let chunk = match item {
             Ok(c) => {
                 if debug {
-                    eprintln!("Received chunk of size: {}", c.len());
+                    eprintln!("Received chunk of size: {}", c.as_ref().len());
                 }
                 c
             },
@@ -110,7 +335,7 @@ async fn process_stream_response(
             }
         };
         
-        buffer.extend_from_slice(&chunk);
+        buffer.extend_from_slice(chunk.as_ref());
         
         // Process complete JSON objects from the buffer
         let mut start = 0;
@@ -129,14 +354,32 @@ async fn process_stream_response(
                                          response_count, response.response.len(), response.done);
                             }
                             
-                            // Send response to output channel
-                            tx.send(response.response).await?;
+                            if first_token.is_none() && !response.response.is_empty() {
+                                first_token = Some(started.elapsed());
+                            }
                             
                             if response.done {
                                 if debug {
                                     eprintln!("Final response received, context length: {}", response.context.len());
                                 }
-                                context = response.context;
+                                generation = Generation {
+                                    stats: GenerationStats::from_response(&response, first_token),
+                                    context: response.context,
+                                    model: response.model,
+                                    created_at: response.created_at,
+                                    done_reason: response.done_reason,
+                                };
+                            }
+                            
+                            // Reasoning arrives in its own field when `think` is enabled,
+                            // otherwise it is inlined in the response as <think> tags
+                            if let Some(thinking) = response.thinking.filter(|t| !t.is_empty()) {
+                                tx.send(Chunk::Thinking(thinking)).await?;
+                            }
+                            
+                            // Send response to output channel
+                            for chunk in think_filter.push(&response.response) {
+                                tx.send(chunk).await?;
                             }
                         },
                         Err(e) => {
@@ -166,10 +409,14 @@ async fn process_stream_response(
         }
     }
     
+    for chunk in think_filter.finish() {
+        tx.send(chunk).await?;
+    }
+    
     if debug {
         eprintln!("Response stream ended, processed {} responses", response_count);
     }
-    Ok(context)
+    Ok(generation)
 }
 
 pub fn generate_first_request<'a>(
@@ -182,6 +429,8 @@ pub fn generate_first_request<'a>(
         system: &config.prompt,
         context: None,
         stream: true,
+        keep_alive: config.keep_alive.as_deref().map(KeepAlive::parse),
+        think: config.think,
     }
 }
 
@@ -196,5 +445,7 @@ pub fn generate_request<'a>(
         system: &config.prompt,
         context: Some(context),
         stream: true,
+        keep_alive: config.keep_alive.as_deref().map(KeepAlive::parse),
+        think: config.think,
     }
 }
@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::app::AppContext;
+
+/// Exit code for a run ended by SIGINT, as shells report it.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Cancellation state shared by the handlers and the signal listener.
+///
+/// Every request runs under a token from [`Cancellation::begin_generation`];
+/// an interrupt cancels all of them at once, which drops their HTTP streams
+/// so the server stops generating. `shutdown` asks the handlers to stop
+/// reading input and finish what is already buffered.
+pub struct Cancellation {
+    round: Mutex<CancellationToken>,
+    shutdown: CancellationToken,
+    in_flight: AtomicUsize,
+    interrupts: AtomicUsize,
+    interrupted: AtomicBool,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self {
+            round: Mutex::new(CancellationToken::new()),
+            shutdown: CancellationToken::new(),
+            in_flight: AtomicUsize::new(0),
+            interrupts: AtomicUsize::new(0),
+            interrupted: AtomicBool::new(false),
+        }
+    }
+    
+    /// Starts a cancellable generation; it stops counting as in flight when dropped.
+    ///
+    /// Interrupts are only forgotten once a generation finishes without being
+    /// cancelled, so a second Ctrl-C still exits after the stream has moved on
+    /// to its next batch.
+    pub fn begin_generation(&self) -> ActiveGeneration<'_> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        ActiveGeneration {
+            token: self.round.lock().unwrap().child_token(),
+            cancellation: self,
+        }
+    }
+    
+    /// Handles a SIGINT, returning true if the process should exit.
+    ///
+    /// With nothing in flight, or on the second interrupt, there is nothing
+    /// left to cancel and the run ends.
+    pub fn interrupt(&self) -> bool {
+        self.interrupted.store(true, Ordering::SeqCst);
+        let interrupts = self.interrupts.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.in_flight.load(Ordering::SeqCst) == 0 || interrupts > 1 {
+            return true;
+        }
+        let round = std::mem::replace(&mut *self.round.lock().unwrap(), CancellationToken::new());
+        round.cancel();
+        false
+    }
+    
+    pub fn was_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+    
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+    
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+    
+    /// Cancelled once the handlers should stop reading input.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+}
+
+impl Default for Cancellation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A request that an interrupt can cancel.
+pub struct ActiveGeneration<'a> {
+    token: CancellationToken,
+    cancellation: &'a Cancellation,
+}
+
+impl ActiveGeneration<'_> {
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for ActiveGeneration<'_> {
+    fn drop(&mut self) {
+        if !self.token.is_cancelled() {
+            self.cancellation.interrupts.store(0, Ordering::SeqCst);
+        }
+        self.cancellation.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Listens for SIGINT and SIGTERM for the rest of the run.
+///
+/// The first SIGINT cancels the generations in flight; a second one, or one
+/// while nothing is being generated, exits with code 130. SIGTERM lets the
+/// handlers flush what they have buffered and exit normally.
+pub fn listen(ctx: Arc<AppContext>) {
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(signal) => Some(signal),
+            Err(e) => {
+                eprintln!("Error installing SIGTERM handler: {}", e);
+                None
+            }
+        };
+        
+        loop {
+            #[cfg(unix)]
+            let terminated = async {
+                match &mut terminate {
+                    Some(signal) => signal.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+            #[cfg(not(unix))]
+            let terminated = std::future::pending::<Option<()>>();
+            
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => {
+                    if let Err(e) = result {
+                        eprintln!("Error listening for Ctrl-C: {}", e);
+                        return;
+                    }
+                    if ctx.cancellation.interrupt() {
+                        // Leave the terminal on a fresh line
+                        eprintln!();
+                        std::process::exit(INTERRUPTED_EXIT_CODE);
+                    }
+                    if ctx.config.debug {
+                        eprintln!("Interrupted, cancelling the current generation");
+                    }
+                }
+                _ = terminated => {
+                    if ctx.config.debug {
+                        eprintln!("Terminated, flushing buffered input");
+                    }
+                    ctx.cancellation.shutdown();
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::backpressure::DropCounters;
+use crate::config::Config;
+use crate::requests::{Generation, OllamaResponse};
+
+const NANOS_PER_MILLI: f64 = 1_000_000.0;
+
+/// Timing and token counts for a single generation.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GenerationStats {
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub tokens_per_second: Option<f64>,
+    pub time_to_first_token_ms: Option<f64>,
+    pub load_ms: Option<f64>,
+    pub prompt_eval_ms: Option<f64>,
+    pub eval_ms: Option<f64>,
+    pub total_ms: Option<f64>,
+}
+
+impl GenerationStats {
+    /// Builds the stats from the final (`done`) response of a stream.
+    pub fn from_response(response: &OllamaResponse, first_token: Option<Duration>) -> Self {
+        let to_ms = |ns: Option<u64>| ns.map(|ns| ns as f64 / NANOS_PER_MILLI);
+        let tokens_per_second = match (response.eval_count, response.eval_duration) {
+            (Some(count), Some(duration)) if duration > 0 => {
+                Some(count as f64 / (duration as f64 / 1_000_000_000.0))
+            }
+            _ => None,
+        };
+        
+        Self {
+            prompt_tokens: response.prompt_eval_count,
+            completion_tokens: response.eval_count,
+            tokens_per_second,
+            time_to_first_token_ms: first_token.map(|d| d.as_secs_f64() * 1000.0),
+            load_ms: to_ms(response.load_duration),
+            prompt_eval_ms: to_ms(response.prompt_eval_duration),
+            eval_ms: to_ms(response.eval_duration),
+            total_ms: to_ms(response.total_duration),
+        }
+    }
+    
+    /// One-line human readable summary for stderr.
+    pub fn summary(&self) -> String {
+        format!(
+            "prompt tokens: {}, completion tokens: {}, {} tok/s, first token: {}, load: {}, total: {}",
+            fmt_count(self.prompt_tokens),
+            fmt_count(self.completion_tokens),
+            self.tokens_per_second.map_or("-".to_string(), |t| format!("{:.1}", t)),
+            fmt_ms(self.time_to_first_token_ms),
+            fmt_ms(self.load_ms),
+            fmt_ms(self.total_ms),
+        )
+    }
+}
+
+fn fmt_count(count: Option<u64>) -> String {
+    count.map_or("-".to_string(), |c| c.to_string())
+}
+
+fn fmt_ms(ms: Option<f64>) -> String {
+    match ms {
+        Some(ms) if ms >= 1000.0 => format!("{:.2}s", ms / 1000.0),
+        Some(ms) => format!("{:.0}ms", ms),
+        None => "-".to_string(),
+    }
+}
+
+/// Running totals across every response in a run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatsTotals {
+    pub responses: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub eval_ms: f64,
+    pub total_ms: f64,
+}
+
+impl StatsTotals {
+    fn add(&mut self, stats: &GenerationStats) {
+        self.responses += 1;
+        self.prompt_tokens += stats.prompt_tokens.unwrap_or(0);
+        self.completion_tokens += stats.completion_tokens.unwrap_or(0);
+        self.eval_ms += stats.eval_ms.unwrap_or(0.0);
+        self.total_ms += stats.total_ms.unwrap_or(0.0);
+    }
+    
+    fn tokens_per_second(&self) -> Option<f64> {
+        (self.eval_ms > 0.0).then(|| self.completion_tokens as f64 / (self.eval_ms / 1000.0))
+    }
+    
+    pub fn summary(&self) -> String {
+        format!(
+            "{} responses, prompt tokens: {}, completion tokens: {}, {} tok/s, total: {}",
+            self.responses,
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.tokens_per_second().map_or("-".to_string(), |t| format!("{:.1}", t)),
+            fmt_ms(Some(self.total_ms)),
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct StatsRecord<'a> {
+    timestamp_ms: u128,
+    batch: u64,
+    model: &'a str,
+    created_at: &'a str,
+    done_reason: Option<&'a str>,
+    #[serde(flatten)]
+    stats: &'a GenerationStats,
+    totals: &'a StatsTotals,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<&'a DropCounters>,
+}
+
+/// Reports generation statistics to stderr (`--stats`) and/or a JSON Lines file (`--stats-json`).
+pub struct StatsReporter {
+    print: bool,
+    json_path: Option<String>,
+    aggregate: bool,
+    totals: StatsTotals,
+    input: Option<DropCounters>,
+}
+
+impl StatsReporter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            // A dry run has no generations to report
+            print: config.stats && !config.dry_run,
+            json_path: config.stats_json.clone().filter(|_| !config.dry_run),
+            aggregate: config.stream,
+            totals: StatsTotals::default(),
+            input: None,
+        }
+    }
+    
+    /// Updates the stream input counters reported alongside the next response.
+    pub fn set_input_counters(&mut self, counters: DropCounters) {
+        self.input = Some(counters);
+    }
+    
+    pub fn record(&mut self, generation: &Generation) -> Result<()> {
+        let stats = &generation.stats;
+        self.totals.add(stats);
+        
+        if self.print {
+            eprintln!("[stats] {}", stats.summary());
+            if self.aggregate {
+                eprintln!("[stats] total: {}", self.totals.summary());
+            }
+            if let Some(input) = self.input.as_ref().filter(|c| !c.is_empty()) {
+                eprintln!(
+                    "[stats] input: {} lines received, {} dropped, {} coalesced",
+                    input.received, input.dropped, input.coalesced
+                );
+            }
+        }
+        
+        if let Some(path) = &self.json_path {
+            let record = StatsRecord {
+                timestamp_ms: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default(),
+                batch: self.totals.responses,
+                model: &generation.model,
+                created_at: &generation.created_at,
+                done_reason: generation.done_reason.as_deref(),
+                stats,
+                totals: &self.totals,
+                input: self.input.as_ref(),
+            };
+            append_json_line(path, &record)?;
+        }
+        
+        Ok(())
+    }
+}
+
+fn append_json_line<T: Serialize>(path: &str, value: &T) -> Result<()> {
+    let expanded = shellexpand::tilde(path);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(Path::new(expanded.as_ref()))
+        .map_err(|e| anyhow::anyhow!("Error opening stats file {}: {}", path, e))?;
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
@@ -1,41 +1,114 @@
 use anyhow::Result;
-use std::io::{self, BufRead, BufReader};
-use tokio::io::AsyncBufReadExt;
+use csv::StringRecord;
+use futures::StreamExt;
+use std::io::Read;
+use std::time::Duration;
+use tokio::io::AsyncRead;
 use tokio::sync::mpsc;
+use tokio_util::codec::{Decoder, FramedRead};
+use tokio_util::sync::CancellationToken;
 
-pub async fn stream_read_stdin(tx: mpsc::Sender<String>) -> Result<()> {
-    let stdin = io::stdin();
-    let reader = BufReader::new(stdin);
+use crate::backpressure::InputQueue;
+use crate::decode::{DecodingReader, InputDecoding};
+use crate::framing::RecordCodec;
+use crate::limits::InputLimit;
+use crate::table::TableFormat;
+
+/// Stops early, as if input had ended, once `shutdown` is cancelled.
+///
+/// A record the codec is still assembling is queued once no input has arrived
+/// for `idle`, so a live stream's last event is not held back.
+pub async fn stream_read<R: AsyncRead + Unpin>(
+    input: R,
+    queue: &InputQueue,
+    codec: RecordCodec,
+    idle: Duration,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut records = FramedRead::new(input, codec);
     
-    for line in reader.lines() {
-        match line {
-            Ok(text) => {
-                tx.send(text).await?;
+    loop {
+        let next = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            next = tokio::time::timeout(idle, records.next()) => next,
+        };
+        match next {
+            Ok(Some(record)) => {
+                let record = record.map_err(|e| anyhow::anyhow!("Error reading input: {}", e))?;
+                queue.push(record).await;
             }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Error reading from stdin: {}", e));
+            Ok(None) => return Ok(()),
+            Err(_) => {
+                if let Some(record) = records.decoder_mut().flush_idle() {
+                    queue.push(record).await;
+                }
             }
         }
     }
     
+    for record in drain(&mut records)? {
+        queue.push(record).await;
+    }
     Ok(())
 }
 
-pub async fn oneshot_read_stdin() -> Result<Vec<String>> {
-    let stdin = io::stdin();
-    let reader = BufReader::new(stdin);
-    let mut buffer = Vec::new();
+pub async fn oneshot_read<R: AsyncRead + Unpin>(
+    input: R,
+    codec: RecordCodec,
+    mut limit: InputLimit,
+    shutdown: CancellationToken,
+) -> Result<Vec<String>> {
+    let mut records = FramedRead::new(input, codec);
     
-    for line in reader.lines() {
-        match line {
-            Ok(text) => {
-                buffer.push(text);
-            }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Error reading from stdin: {}", e));
+    loop {
+        let next = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            next = records.next() => next,
+        };
+        match next {
+            Some(record) => {
+                let record = record.map_err(|e| anyhow::anyhow!("Error reading input: {}", e))?;
+                limit.push(record)?;
             }
+            None => return Ok(limit.finish()),
+        }
+    }
+    
+    for record in drain(&mut records)? {
+        limit.push(record)?;
+    }
+    Ok(limit.finish())
+}
+
+/// Ends the input early: whatever the codec still holds is framed as if input had ended here.
+fn drain<R>(records: &mut FramedRead<R, RecordCodec>) -> Result<Vec<String>> {
+    let mut buffer = std::mem::take(records.read_buffer_mut());
+    let codec = records.decoder_mut();
+    let mut drained = Vec::new();
+    while let Some(record) = codec.decode_eof(&mut buffer)? {
+        drained.push(record);
+    }
+    Ok(drained)
+}
+
+/// Reads CSV/TSV rows (header first) on a blocking thread, stopping at the first error.
+pub fn table_read<R: Read>(input: R, format: TableFormat, decoding: InputDecoding, tx: mpsc::Sender<Result<StringRecord>>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(format.delimiter())
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(DecodingReader::new(input, decoding));
+    
+    for record in reader.records() {
+        let record = record.map_err(|e| anyhow::anyhow!("Error reading table: {}", e));
+        let failed = record.is_err();
+        if tx.blocking_send(record).is_err() || failed {
+            return;
         }
     }
     
-    Ok(buffer)
+    let replaced = reader.get_ref().replaced();
+    if replaced > 0 {
+        eprintln!("Replaced {} invalid bytes in the input with U+FFFD", replaced);
+    }
 }
@@ -0,0 +1,150 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use csv::StringRecord;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::config::Config;
+
+/// Delimited formats accepted by the tabular mode.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    /// Comma-separated values
+    Csv,
+    /// Tab-separated values
+    Tsv,
+}
+
+impl TableFormat {
+    pub fn delimiter(self) -> u8 {
+        match self {
+            Self::Csv => b',',
+            Self::Tsv => b'\t',
+        }
+    }
+}
+
+/// A column appended to the table, answered with its own system prompt if one is given.
+#[derive(Debug, Clone)]
+pub struct OutputColumn {
+    pub name: String,
+    pub prompt: Option<String>,
+}
+
+impl OutputColumn {
+    /// Parses `NAME` or `NAME=PROMPT`.
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once('=') {
+            Some((name, prompt)) => Self {
+                name: name.trim().to_string(),
+                prompt: Some(prompt.to_string()),
+            },
+            None => Self {
+                name: spec.trim().to_string(),
+                prompt: None,
+            },
+        }
+    }
+}
+
+/// `{{row.NAME}}`, where NAME is the header text as written, spaces included.
+fn placeholder_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\{\{\s*row\.([^}]+?)\s*\}\}").expect("valid template pattern"))
+}
+
+/// Turns a row into the text sent to the model.
+///
+/// With a template, `{{row.title}}` is replaced by the row's `title` cell;
+/// without one the row is sent as `column: value` lines.
+pub struct RowTemplate {
+    template: Option<String>,
+    headers: StringRecord,
+}
+
+impl RowTemplate {
+    pub fn new(template: Option<&str>, headers: StringRecord) -> Result<Self> {
+        if let Some(template) = template {
+            let unknown: Vec<&str> = placeholder_pattern()
+                .captures_iter(template)
+                .map(|caps| caps.get(1).unwrap().as_str())
+                .filter(|name| !headers.iter().any(|h| h.trim() == *name))
+                .collect();
+            if !unknown.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Row template refers to unknown columns: {} (available: {})",
+                    unknown.join(", "),
+                    headers.iter().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        
+        Ok(Self {
+            template: template.map(str::to_string),
+            headers,
+        })
+    }
+    
+    pub fn render(&self, row: &StringRecord) -> String {
+        let cell = |name: &str| {
+            self.headers
+                .iter()
+                .position(|h| h.trim() == name)
+                .and_then(|i| row.get(i))
+                .unwrap_or("")
+                .to_string()
+        };
+        
+        match &self.template {
+            Some(template) => placeholder_pattern()
+                .replace_all(template, |caps: &Captures| cell(&caps[1]))
+                .into_owned(),
+            None => self.headers
+                .iter()
+                .zip(row.iter())
+                .map(|(header, value)| format!("{}: {}", header, value))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// Settings for `--csv` / `--tsv`.
+pub struct TableSettings {
+    pub format: TableFormat,
+    pub template: Option<String>,
+    pub columns: Vec<OutputColumn>,
+    pub concurrency: usize,
+}
+
+impl TableSettings {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let format = config.table?;
+        let mut columns: Vec<OutputColumn> = config.columns.iter().map(|c| OutputColumn::parse(c)).collect();
+        if columns.is_empty() {
+            columns.push(OutputColumn::parse("answer"));
+        }
+        
+        Some(Self {
+            format,
+            template: config.row_template.clone(),
+            columns,
+            concurrency: config.concurrency.max(1),
+        })
+    }
+}
+
+/// Serialises one record in the table's format, quoting cells as needed.
+pub fn write_record(format: TableFormat, record: &StringRecord) -> Result<String> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.delimiter())
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    writer.write_record(record)?;
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| anyhow::anyhow!("Error writing row: {}", e))?;
+    Ok(String::from_utf8(bytes)?.trim_end_matches('\n').to_string())
+}
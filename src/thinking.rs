@@ -0,0 +1,79 @@
+use crate::requests::Chunk;
+
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+
+/// Separates `<think>...</think>` reasoning from the answer in streamed text.
+///
+/// Tags may be split across chunks, so any trailing text that could be the
+/// start of a tag is held back until the next chunk decides it.
+#[derive(Debug, Default)]
+pub struct ThinkFilter {
+    pending: String,
+    inside: bool,
+    trim_next: bool,
+}
+
+impl ThinkFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn push(&mut self, text: &str) -> Vec<Chunk> {
+        self.pending.push_str(text);
+        let mut chunks = Vec::new();
+        
+        loop {
+            let tag = if self.inside { CLOSE_TAG } else { OPEN_TAG };
+            if let Some(pos) = self.pending.find(tag) {
+                let segment: String = self.pending.drain(..pos + tag.len()).collect();
+                self.emit(&segment[..pos], &mut chunks);
+                self.inside = !self.inside;
+                // Models put blank lines around the tags, which are dropped with them
+                self.trim_next = true;
+                continue;
+            }
+            
+            let keep = partial_tag_len(&self.pending, tag);
+            let segment: String = self.pending.drain(..self.pending.len() - keep).collect();
+            self.emit(&segment, &mut chunks);
+            break;
+        }
+        
+        chunks
+    }
+    
+    /// Releases anything held back once the response has ended.
+    pub fn finish(&mut self) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        let rest = std::mem::take(&mut self.pending);
+        self.emit(&rest, &mut chunks);
+        chunks
+    }
+    
+    fn emit(&mut self, segment: &str, chunks: &mut Vec<Chunk>) {
+        let segment = if self.trim_next {
+            segment.trim_start()
+        } else {
+            segment
+        };
+        if segment.is_empty() {
+            return;
+        }
+        
+        self.trim_next = false;
+        if self.inside {
+            chunks.push(Chunk::Thinking(segment.to_string()));
+        } else {
+            chunks.push(Chunk::Text(segment.to_string()));
+        }
+    }
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `tag`.
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&len| text.ends_with(&tag[..len]))
+        .unwrap_or(0)
+}
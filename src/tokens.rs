@@ -0,0 +1,7 @@
+/// Rough token count for budgeting: about four characters per token for English text and code.
+///
+/// This only needs to be in the right ballpark to size batches and context; the server
+/// reports exact counts in its statistics.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
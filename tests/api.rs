@@ -0,0 +1,35 @@
+mod support;
+
+use futures::StreamExt;
+
+use inlama::{Chunk, Inlama};
+use support::{test_config, MockServer};
+
+#[test]
+fn stream_is_sent_on_first_poll_not_when_created() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start());
+    let inlama = Inlama::builder().config(test_config(server.url())).build().unwrap();
+    
+    // Created outside the runtime, which used to panic in tokio::spawn
+    let chunks = inlama.stream("hello");
+    assert!(server.requests().is_empty());
+    
+    let text: String = runtime.block_on(
+        chunks
+            .map(|chunk| match chunk.unwrap() {
+                Chunk::Text(text) => text,
+                Chunk::Thinking(_) => String::new(),
+            })
+            .collect(),
+    );
+    assert_eq!(text, "Answer: hello");
+}
+
+#[test]
+fn builder_sets_the_model_and_server() {
+    let inlama = Inlama::builder().model("llama3.2").url("http://ollama:11434").build().unwrap();
+    
+    assert_eq!(inlama.config().model, "llama3.2");
+    assert_eq!(inlama.config().url, ["http://ollama:11434"]);
+}
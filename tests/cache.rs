@@ -0,0 +1,180 @@
+mod support;
+
+use serde_json::Value;
+use std::fs;
+use std::thread;
+
+use inlama::cache::ResponseCache;
+use inlama::handler::oneshot_handler;
+use inlama::output::OutputFormat;
+use support::{context, temp_dir, test_config, Captured, MockServer};
+
+fn cached_config(url: &str, name: &str) -> inlama::Config {
+    let mut config = test_config(url);
+    config.cache = true;
+    config.cache_dir = Some(temp_dir(name).display().to_string());
+    config
+}
+
+async fn oneshot(config: inlama::Config, input: &'static [u8]) -> String {
+    let output = Captured::default();
+    oneshot_handler(context(config), input, output.destination()).await.unwrap();
+    output.text()
+}
+
+#[tokio::test]
+async fn repeated_requests_are_answered_from_the_cache() {
+    let server = MockServer::start().await;
+    let mut config = cached_config(server.url(), "cache-hit");
+    config.output = OutputFormat::Jsonl;
+    
+    let first: Value = serde_json::from_str(oneshot(config.clone(), b"same\n").await.trim()).unwrap();
+    let second: Value = serde_json::from_str(oneshot(config.clone(), b"same\n").await.trim()).unwrap();
+    oneshot(config, b"different\n").await;
+    
+    assert_eq!(server.requests_to("/api/generate").len(), 2);
+    for field in ["response", "model", "done_reason", "input_hash"] {
+        assert_eq!(first[field], second[field], "{}", field);
+    }
+    assert_eq!(first["stats"]["completion_tokens"], second["stats"]["completion_tokens"]);
+    assert_eq!(second["response"], "Answer: same");
+}
+
+#[tokio::test]
+async fn refresh_asks_again_and_no_cache_skips_it() {
+    let server = MockServer::start().await;
+    let config = cached_config(server.url(), "cache-refresh");
+    oneshot(config.clone(), b"same\n").await;
+    
+    let mut refresh = config.clone();
+    refresh.refresh = true;
+    oneshot(refresh, b"same\n").await;
+    let mut disabled = config.clone();
+    disabled.cache = false;
+    oneshot(disabled, b"same\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 3);
+    
+    oneshot(config, b"same\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 3);
+}
+
+#[tokio::test]
+async fn expired_answers_are_fetched_again() {
+    let server = MockServer::start().await;
+    let mut config = cached_config(server.url(), "cache-ttl");
+    config.cache_ttl = Some(60);
+    
+    oneshot(config.clone(), b"same\n").await;
+    oneshot(config.clone(), b"same\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 1);
+    
+    backdate(config.cache_dir.as_deref().unwrap(), 60);
+    oneshot(config, b"same\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 2);
+}
+
+/// Makes every cached answer `seconds` older, instead of waiting for it to expire.
+fn backdate(dir: &str, seconds: u64) {
+    let path = std::path::Path::new(dir).join("index.json");
+    let mut index: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    for entry in index["entries"].as_object_mut().unwrap().values_mut() {
+        entry["created"] = (entry["created"].as_u64().unwrap() - seconds).into();
+    }
+    fs::write(&path, index.to_string()).unwrap();
+}
+
+#[tokio::test]
+async fn least_recently_used_answers_are_evicted() {
+    let server = MockServer::start().await;
+    let mut config = cached_config(server.url(), "cache-lru");
+    // Room for two of the mock's answers, but not three
+    config.cache_max_bytes = Some(1000);
+    
+    oneshot(config.clone(), b"a\n").await;
+    oneshot(config.clone(), b"b\n").await;
+    oneshot(config.clone(), b"a\n").await;
+    oneshot(config.clone(), b"c\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 3);
+    
+    // "b" was used least recently, so it made room for "c"
+    oneshot(config.clone(), b"a\n").await;
+    oneshot(config.clone(), b"c\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 3);
+    oneshot(config, b"b\n").await;
+    assert_eq!(server.requests_to("/api/generate").len(), 4);
+}
+
+#[tokio::test]
+async fn clear_removes_every_answer() {
+    let server = MockServer::start().await;
+    let config = cached_config(server.url(), "cache-clear");
+    oneshot(config.clone(), b"a\n").await;
+    oneshot(config.clone(), b"b\n").await;
+    
+    assert_eq!(ResponseCache::open(&config).clear().unwrap(), 2);
+    oneshot(config, b"a\n").await;
+    
+    assert_eq!(server.requests_to("/api/generate").len(), 3);
+}
+
+#[test]
+fn clear_leaves_other_files_alone() {
+    let dir = temp_dir("cache-clear-others");
+    let mut config = test_config("http://127.0.0.1:1");
+    config.cache_dir = Some(dir.display().to_string());
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("events.ndjson"), b"{}\n").unwrap();
+    fs::write(dir.join(format!("{}.ndjson", key(1))), b"answer\n").unwrap();
+    
+    assert_eq!(ResponseCache::open(&config).clear().unwrap(), 1);
+    assert!(dir.join("events.ndjson").exists());
+    assert!(!dir.join(format!("{}.ndjson", key(1))).exists());
+}
+
+fn key(i: usize) -> String {
+    format!("{:064x}", i)
+}
+
+#[test]
+fn concurrent_writers_do_not_lose_index_entries() {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.cache = true;
+    config.cache_dir = Some(temp_dir("cache-concurrent").display().to_string());
+    
+    // Separate caches stand in for separate runs sharing the directory
+    let writers: Vec<_> = (0..8)
+        .map(|writer| {
+            let config = config.clone();
+            thread::spawn(move || {
+                let cache = ResponseCache::open(&config);
+                for i in 0..10 {
+                    cache.store(&key(writer * 10 + i), b"answer\n").unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    
+    let cache = ResponseCache::open(&config);
+    for i in 0..80 {
+        assert!(cache.lookup(&key(i)).unwrap().is_some(), "lost entry {}", i);
+    }
+}
+
+#[test]
+fn answers_missing_from_the_index_are_pruned() {
+    let dir = temp_dir("cache-orphans");
+    let mut config = test_config("http://127.0.0.1:1");
+    config.cache = true;
+    config.cache_dir = Some(dir.display().to_string());
+    config.cache_max_bytes = Some(1000);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(format!("{}.ndjson", key(1))), vec![b'x'; 5000]).unwrap();
+    
+    ResponseCache::open(&config).store(&key(2), b"answer\n").unwrap();
+    
+    assert!(!dir.join(format!("{}.ndjson", key(1))).exists());
+    assert!(dir.join(format!("{}.ndjson", key(2))).exists());
+}
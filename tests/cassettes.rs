@@ -0,0 +1,119 @@
+mod support;
+
+use serde_json::{json, Value};
+use std::fs;
+
+use inlama::cassette::ReplayMiss;
+use inlama::handler::{oneshot_handler, stream_handler};
+use inlama::output::OutputFormat;
+use support::{context, generate_line, temp_dir, test_config, Captured, MockServer, Reply};
+
+/// Nothing listens here, so any request that reaches the network fails.
+const OFFLINE_URL: &str = "http://127.0.0.1:1";
+
+async fn oneshot(config: inlama::Config, input: &'static [u8]) -> String {
+    let output = Captured::default();
+    oneshot_handler(context(config), input, output.destination()).await.unwrap();
+    output.text()
+}
+
+#[tokio::test]
+async fn replays_a_recording_without_the_server() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("replay");
+    let mut config = test_config(server.url());
+    config.record = Some(dir.display().to_string());
+    let recorded = oneshot(config, b"hello\n").await;
+    
+    let mut files: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 2);
+    assert!(files[0].ends_with(".json") && files[1].ends_with(".ndjson"), "{:?}", files);
+    let request: Value = serde_json::from_str(&fs::read_to_string(dir.join(&files[0])).unwrap()).unwrap();
+    assert_eq!(request["prompt"], "hello");
+    
+    let mut config = test_config(OFFLINE_URL);
+    config.replay = Some(dir.display().to_string());
+    let replayed = oneshot(config, b"hello\n").await;
+    
+    assert_eq!(recorded, "Answer: hello\n");
+    assert_eq!(replayed, recorded);
+    assert_eq!(server.requests_to("/api/generate").len(), 1);
+}
+
+#[tokio::test]
+async fn answers_cut_off_before_the_final_line_are_not_recorded() {
+    let server = MockServer::start().await;
+    server.script("/api/generate", Reply::lines(&[generate_line(json!({ "response": "partial" }))]));
+    let dir = temp_dir("cut-off");
+    let mut config = test_config(server.url());
+    config.record = Some(dir.display().to_string());
+    oneshot(config, b"hello\n").await;
+    
+    let files: Vec<_> = fs::read_dir(&dir).map(|entries| entries.collect()).unwrap_or_default();
+    assert!(files.is_empty(), "{:?}", files);
+}
+
+#[tokio::test]
+async fn a_different_prompt_is_a_miss() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("miss");
+    let mut config = test_config(server.url());
+    config.record = Some(dir.display().to_string());
+    oneshot(config, b"hello\n").await;
+    
+    let mut config = test_config(OFFLINE_URL);
+    config.replay = Some(dir.display().to_string());
+    config.prompt = "Another prompt".to_string();
+    config.output = OutputFormat::Jsonl;
+    let output = oneshot(config, b"hello\n").await;
+    
+    let envelope: Value = serde_json::from_str(output.trim()).unwrap();
+    let error = envelope["error"].as_str().unwrap();
+    assert!(error.contains("No recording of this request"), "{}", error);
+}
+
+#[tokio::test]
+async fn live_misses_are_sent_and_recorded() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("live");
+    let mut config = test_config(server.url());
+    config.record = Some(dir.display().to_string());
+    config.replay = Some(dir.display().to_string());
+    config.replay_miss = ReplayMiss::Live;
+    
+    let first = oneshot(config.clone(), b"hello\n").await;
+    let second = oneshot(config, b"hello\n").await;
+    
+    assert_eq!(first, "Answer: hello\n");
+    assert_eq!(second, first);
+    assert_eq!(server.requests_to("/api/generate").len(), 1);
+}
+
+#[tokio::test]
+async fn replays_a_whole_conversation() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("conversation");
+    let mut config = test_config(server.url());
+    config.stream = true;
+    config.batch_max_lines = Some(1);
+    config.record = Some(dir.display().to_string());
+    let recorded = Captured::default();
+    stream_handler(context(config.clone()), &b"one\ntwo\n"[..], recorded.destination())
+        .await
+        .unwrap();
+    
+    config.url = vec![OFFLINE_URL.to_string()];
+    config.record = None;
+    config.replay = Some(dir.display().to_string());
+    let replayed = Captured::default();
+    stream_handler(context(config), &b"one\ntwo\n"[..], replayed.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(recorded.text(), "Answer: one\nAnswer: two\n");
+    assert_eq!(replayed.text(), recorded.text());
+}
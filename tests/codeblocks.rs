@@ -0,0 +1,42 @@
+mod support;
+
+use std::fs;
+
+use inlama::codeblocks::{parse_code_blocks, CodeExtraction};
+use support::{temp_dir, test_config};
+
+fn written_names(answer: &str, name: &str) -> (std::path::PathBuf, Vec<String>) {
+    let dir = temp_dir(name);
+    let mut config = test_config("http://127.0.0.1:1");
+    config.write_blocks = Some(dir.join("blocks").display().to_string());
+    let extraction = CodeExtraction::from_config(&config).unwrap();
+    
+    let written = extraction.write_blocks(&parse_code_blocks(answer)).unwrap();
+    let names = written
+        .iter()
+        .map(|path| {
+            assert_eq!(path.parent(), Some(dir.join("blocks").as_path()), "{}", path.display());
+            path.file_name().unwrap().to_string_lossy().into_owned()
+        })
+        .collect();
+    (dir, names)
+}
+
+#[test]
+fn hostile_info_strings_stay_inside_the_directory() {
+    let answer = "```../../escape\none\n```\n```/tmp/absolute\ntwo\n```\n```rust:../../src/main.rs\nthree\n```\n```..\nfour\n```\n";
+    let (dir, names) = written_names(answer, "blocks-hostile");
+    
+    assert_eq!(names, ["block-1.txt", "block-2.txt", "main.rs", "block-4.txt"]);
+    // Nothing was written next to the blocks directory
+    let siblings: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(siblings, ["blocks"]);
+}
+
+#[test]
+fn blocks_are_named_after_hints_or_their_language() {
+    let answer = "```python title=\"fetch.py\"\nprint(1)\n```\n```c++\nint x;\n```\n```\nplain\n```\n";
+    let (_, names) = written_names(answer, "blocks-names");
+    
+    assert_eq!(names, ["fetch.py", "c++-2.cpp", "block-3.txt"]);
+}
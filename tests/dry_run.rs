@@ -0,0 +1,124 @@
+mod support;
+
+use serde_json::Value;
+
+use inlama::handler::{oneshot_handler, stream_handler, table_handler};
+use inlama::output::OutputFormat;
+use inlama::table::TableFormat;
+use inlama::requests::{dry_run_report, generate_first_request};
+use support::{context, temp_dir, test_config, Captured, MockServer};
+
+#[tokio::test]
+async fn dry_run_never_contacts_the_server() {
+    let server = MockServer::start().await;
+    let mut config = test_config(server.url());
+    config.dry_run = true;
+    config.cache = true;
+    let recordings = temp_dir("dry-run");
+    config.record = Some(recordings.display().to_string());
+    
+    let output = Captured::default();
+    oneshot_handler(context(config), &b"hello\n"[..], output.destination()).await.unwrap();
+    
+    assert!(server.requests().is_empty());
+    assert_eq!(std::fs::read_dir(&recordings).unwrap().count(), 0);
+    let report: Value = serde_json::from_str(&output.text()).unwrap();
+    assert_eq!(report["request"]["prompt"], "hello");
+}
+
+#[tokio::test]
+async fn reports_go_to_the_destination_in_every_format() {
+    for format in [OutputFormat::Text, OutputFormat::Jsonl] {
+        let mut config = test_config("http://127.0.0.1:1");
+        config.dry_run = true;
+        config.output = format;
+        config.redact = true;
+        config.unredact = true;
+        
+        let output = Captured::default();
+        oneshot_handler(context(config), &b"mail bob@example.com\n"[..], output.destination())
+            .await
+            .unwrap();
+        
+        let text = output.text();
+        assert!(text.ends_with("}\n"), "{:?}", text);
+        let report: Value = serde_json::from_str(&text).unwrap();
+        // The report shows what would be sent, so placeholders stay in place
+        assert_eq!(report["request"]["prompt"], "mail <EMAIL_1>", "{:?}", format);
+    }
+}
+
+#[tokio::test]
+async fn dry_run_leaves_output_files_alone() {
+    let dir = temp_dir("dry-run-files");
+    std::fs::create_dir_all(&dir).unwrap();
+    let tee = dir.join("answers.txt");
+    std::fs::write(&tee, "earlier answers\n").unwrap();
+    let mut config = test_config("http://127.0.0.1:1");
+    config.dry_run = true;
+    config.tee = Some(tee.display().to_string());
+    config.stats_json = Some(dir.join("stats.jsonl").display().to_string());
+    config.write_blocks = Some(dir.join("blocks").display().to_string());
+    
+    oneshot_handler(context(config), &b"hello\n"[..], Captured::default().destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(std::fs::read_to_string(&tee).unwrap(), "earlier answers\n");
+    assert!(!dir.join("stats.jsonl").exists());
+    assert!(!dir.join("blocks").exists());
+}
+
+#[tokio::test]
+async fn stream_batches_are_each_reported() {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.dry_run = true;
+    config.stream = true;
+    config.batch_max_lines = Some(1);
+    
+    let output = Captured::default();
+    stream_handler(context(config), &b"one\ntwo\n"[..], output.destination()).await.unwrap();
+    
+    let prompts: Vec<Value> = serde_json::Deserializer::from_str(&output.text())
+        .into_iter::<Value>()
+        .map(|report| report.unwrap()["request"]["prompt"].clone())
+        .collect();
+    assert_eq!(prompts, ["one", "two"]);
+}
+
+#[tokio::test]
+async fn table_rows_are_reported_instead_of_written() {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.dry_run = true;
+    config.table = Some(TableFormat::Csv);
+    config.row_template = Some("Ticket: {{row.Title}}".to_string());
+    
+    let output = Captured::default();
+    let input = std::io::Cursor::new(b"Title\nDisk full\nLogin broken\n".to_vec());
+    table_handler(context(config), input, output.destination()).await.unwrap();
+    
+    let prompts: Vec<Value> = serde_json::Deserializer::from_str(&output.text())
+        .into_iter::<Value>()
+        .map(|report| report.unwrap()["request"]["prompt"].clone())
+        .collect();
+    assert_eq!(prompts, ["Ticket: Disk full", "Ticket: Login broken"]);
+}
+
+#[test]
+fn report_shows_the_request_and_its_estimated_size() {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.prompt = "Summarise".to_string();
+    let body = "a".repeat(40);
+    let request = generate_first_request(&body, &config);
+    
+    let report: Value = serde_json::from_str(&dry_run_report(&request).unwrap()).unwrap();
+    
+    assert_eq!(report["request"]["model"], "mock");
+    assert_eq!(report["request"]["system"], "Summarise");
+    assert_eq!(report["request"]["prompt"], body);
+    assert_eq!(report["request"]["stream"], true);
+    assert_eq!(report["estimated_tokens"]["system"], 3);
+    assert_eq!(report["estimated_tokens"]["prompt"], 10);
+    assert_eq!(report["estimated_tokens"]["context"], 0);
+    assert_eq!(report["estimated_tokens"]["total"], 13);
+}
@@ -0,0 +1,72 @@
+mod support;
+
+use serde_json::{json, Value};
+
+use inlama::handler::{enrich_handler, oneshot_handler};
+use support::{context, test_config, Captured, MockServer};
+
+fn merge_config(url: &str) -> inlama::Config {
+    let mut config = test_config(url);
+    config.merge_into = Some("out".to_string());
+    config.fields = vec!["message".to_string()];
+    config
+}
+
+fn json_lines(text: &str) -> Vec<Value> {
+    text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn each_record_is_merged_with_its_answer() {
+    let server = MockServer::start().await;
+    let output = Captured::default();
+    let input = b"{\"message\":\"disk full\",\"level\":\"error\"}\n{\"message\":\"login broken\"}\n";
+    
+    enrich_handler(context(merge_config(server.url())), &input[..], output.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(json_lines(&output.text()), [
+        json!({ "message": "disk full", "level": "error", "out": "Answer: disk full" }),
+        json!({ "message": "login broken", "out": "Answer: login broken" }),
+    ]);
+    let prompts: Vec<Value> = server.requests_to("/api/generate").iter().map(|r| r.body["prompt"].clone()).collect();
+    assert_eq!(prompts, ["disk full", "login broken"]);
+}
+
+#[tokio::test]
+async fn a_malformed_line_does_not_swallow_the_records_after_it() {
+    let server = MockServer::start().await;
+    let output = Captured::default();
+    let input = b"{\"message\":\"first\"}\noops {\n{\"message\":\"second\"}\n[\"unclosed\n{\"message\":\"third\"}\n";
+    
+    enrich_handler(context(merge_config(server.url())), &input[..], output.destination())
+        .await
+        .unwrap();
+    
+    let text = output.text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 5, "{:?}", lines);
+    // Lines that aren't JSON pass through untouched
+    assert_eq!(lines[1], "oops {");
+    assert_eq!(lines[3], "[\"unclosed");
+    for (line, message) in [(lines[0], "first"), (lines[2], "second"), (lines[4], "third")] {
+        let record: Value = serde_json::from_str(line).unwrap();
+        assert_eq!(record["out"], format!("Answer: {}", message));
+    }
+}
+
+#[tokio::test]
+async fn jsonl_in_sends_the_selected_fields() {
+    let server = MockServer::start().await;
+    let mut config = test_config(server.url());
+    config.jsonl_in = true;
+    config.fields = vec!["message".to_string(), "host".to_string()];
+    let input = b"{\"message\":\"disk full\",\"host\":\"db1\",\"pid\":7}\n";
+    
+    oneshot_handler(context(config), &input[..], Captured::default().destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(server.requests_to("/api/generate")[0].body["prompt"], "message: disk full\nhost: db1");
+}
@@ -0,0 +1,67 @@
+mod support;
+
+use inlama::limits::{InputLimit, TruncateStrategy};
+use support::test_config;
+
+const BUDGET: usize = 64;
+
+fn limited(strategy: TruncateStrategy, records: &[String]) -> Vec<String> {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.max_input_bytes = Some(BUDGET);
+    config.truncate = strategy;
+    let mut limit = InputLimit::from_config(&config);
+    for record in records {
+        limit.push(record.clone()).unwrap();
+    }
+    limit.finish()
+}
+
+/// Bytes as the budget counts them: each record plus its newline.
+fn size(records: &[String]) -> usize {
+    records.iter().map(|record| record.len() + 1).sum()
+}
+
+fn lines(count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("line {:02}", i)).collect()
+}
+
+#[test]
+fn every_strategy_stays_within_the_budget() {
+    let records = lines(40);
+    for strategy in [TruncateStrategy::Head, TruncateStrategy::Tail, TruncateStrategy::Sample] {
+        let kept = limited(strategy, &records);
+        assert!(!kept.is_empty(), "{:?}", strategy);
+        assert!(size(&kept) <= BUDGET, "{:?} kept {} bytes", strategy, size(&kept));
+    }
+    
+    // The elision marker counts against the budget too
+    let kept = limited(TruncateStrategy::HeadTail, &records);
+    assert!(kept.iter().any(|record| record.starts_with("[...")), "{:?}", kept);
+    assert_eq!(kept.first().map(String::as_str), Some("line 00"));
+    assert!(size(&kept) <= BUDGET, "head+tail kept {} bytes", size(&kept));
+}
+
+#[test]
+fn an_oversized_record_is_cut_to_fit() {
+    let huge = format!("start {} end", "x".repeat(200));
+    let records = vec![huge.clone(), "after".to_string()];
+    
+    let head = limited(TruncateStrategy::Head, &records);
+    assert_eq!(head.len(), 1);
+    assert!(huge.starts_with(&head[0]) && size(&head) <= BUDGET, "{:?}", head);
+    
+    let tail = limited(TruncateStrategy::Tail, &[records[1].clone(), huge.clone()]);
+    assert_eq!(tail.len(), 1);
+    assert!(huge.ends_with(&tail[0]) && size(&tail) <= BUDGET, "{:?}", tail);
+    
+    let sample = limited(TruncateStrategy::Sample, &records);
+    assert!(huge.starts_with(&sample[0]) && size(&sample) <= BUDGET, "{:?}", sample);
+}
+
+#[test]
+fn input_under_the_budget_is_kept_whole() {
+    let records = lines(3);
+    for strategy in [TruncateStrategy::Head, TruncateStrategy::Tail, TruncateStrategy::HeadTail, TruncateStrategy::Sample] {
+        assert_eq!(limited(strategy, &records), records, "{:?}", strategy);
+    }
+}
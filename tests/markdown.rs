@@ -0,0 +1,83 @@
+use inlama::markdown::MarkdownRenderer;
+
+/// Renders `chunks` as if they were streamed one after another.
+fn render(chunks: &[&str]) -> String {
+    let mut renderer = MarkdownRenderer::new();
+    let mut out: String = chunks.iter().map(|chunk| renderer.push(chunk)).collect();
+    out.push_str(&renderer.finish());
+    out
+}
+
+/// `text` without ANSI escape sequences.
+fn plain(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[test]
+fn highlights_fenced_code_by_language() {
+    let out = render(&["```rust\nfn main() {}\n```\n"]);
+    
+    // 24-bit colours come from the syntax highlighter
+    assert!(out.contains("\x1b[38;2;"), "{:?}", out);
+    assert_eq!(plain(&out), "─── rust\nfn main() {}\n───\n");
+}
+
+#[test]
+fn unknown_languages_are_shown_without_highlighting() {
+    let out = render(&["```nosuchlanguage\nsome code\n```\n"]);
+    
+    assert!(!out.contains("\x1b[38;2;"), "{:?}", out);
+    assert!(out.contains("\x1b[36msome code\x1b[0m"), "{:?}", out);
+}
+
+#[test]
+fn fences_split_across_chunks_render_the_same() {
+    let whole = render(&["Before\n```python\nprint('hi')\n```\nAfter\n"]);
+    let split = render(&["Bef", "ore\n``", "`pyt", "hon\npri", "nt('hi')\n`", "``\nAf", "ter"]);
+    
+    assert_eq!(split, whole);
+    assert_eq!(plain(&split), "Before\n─── python\nprint('hi')\n───\nAfter\n");
+}
+
+#[test]
+fn inline_markup_split_across_chunks_is_styled() {
+    let whole = render(&["Some **bold**, *italic* and `code`\n"]);
+    let split = render(&["Some **bo", "ld**, *ita", "lic* and `co", "de`\n"]);
+    
+    assert_eq!(split, whole);
+    assert!(split.contains("\x1b[1mbold\x1b[0m"), "{:?}", split);
+    assert!(split.contains("\x1b[3mitalic\x1b[0m"), "{:?}", split);
+    assert!(split.contains("\x1b[36mcode\x1b[0m"), "{:?}", split);
+    assert_eq!(plain(&split), "Some bold, italic and code\n");
+}
+
+#[test]
+fn tables_are_aligned_once_complete() {
+    let out = render(&["| a | long header |\n|---|---|\n| wide cell | b |\n", "\nafter\n"]);
+    
+    let lines: Vec<String> = plain(&out).lines().map(str::to_string).collect();
+    assert_eq!(lines[0], "a         │ long header");
+    assert_eq!(lines[1], "──────────┼────────────");
+    assert_eq!(lines[2], "wide cell │ b          ");
+}
+
+#[test]
+fn unterminated_code_blocks_are_closed_at_the_end() {
+    let out = render(&["```\nunfinished"]);
+    
+    assert!(out.ends_with("\x1b[0m"), "{:?}", out);
+    assert_eq!(plain(&out), "───\nunfinished\n");
+}
@@ -0,0 +1,156 @@
+mod support;
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use inlama::handler::oneshot_handler;
+use inlama::output::OutputFormat;
+use support::{context, done_line, generate_line, test_config, Captured, Gate, MockServer, Reply};
+
+#[tokio::test]
+async fn answers_the_whole_input_in_one_request() {
+    let server = MockServer::start().await;
+    let output = Captured::default();
+    let ctx = context(test_config(server.url()));
+    
+    oneshot_handler(ctx, &b"first line\nsecond line\n"[..], output.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(output.text(), "Answer: first line\nsecond line\n");
+    let requests = server.requests_to("/api/generate");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].body["prompt"], "first line\nsecond line");
+    assert_eq!(requests[0].body["system"], "Test prompt");
+    assert_eq!(requests[0].body["model"], "mock");
+    assert_eq!(requests[0].body["stream"], true);
+    assert!(requests[0].body.get("context").is_none());
+}
+
+#[tokio::test]
+async fn reassembles_lines_split_across_frames() {
+    let server = MockServer::start().await;
+    server.script("/api/generate", Reply::generate(&["Grüße", " aus ", "東京"]).split_every(7));
+    let output = Captured::default();
+    
+    oneshot_handler(context(test_config(server.url())), &b"hi\n"[..], output.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(output.text(), "Grüße aus 東京\n");
+}
+
+#[tokio::test]
+async fn skips_lines_that_are_not_json() {
+    let server = MockServer::start().await;
+    server.script(
+        "/api/generate",
+        Reply::lines(&[
+            generate_line(json!({ "response": "one" })),
+            "{\"response\": \"trunc".to_string(),
+            generate_line(json!({ "response": " two" })),
+            done_line(&[7]),
+        ]),
+    );
+    let output = Captured::default();
+    
+    oneshot_handler(context(test_config(server.url())), &b"hi\n"[..], output.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(output.text(), "one two\n");
+}
+
+#[tokio::test]
+async fn reports_error_statuses() {
+    let server = MockServer::start().await;
+    server.script("/api/generate", Reply::status(404, "{\"error\":\"model 'mock' not found\"}"));
+    let mut config = test_config(server.url());
+    config.output = OutputFormat::Jsonl;
+    let output = Captured::default();
+    
+    oneshot_handler(context(config), &b"hi\n"[..], output.destination())
+        .await
+        .unwrap();
+    
+    let envelope: Value = serde_json::from_str(output.text().trim()).unwrap();
+    let error = envelope["error"].as_str().unwrap();
+    assert!(error.contains("404"), "{}", error);
+    assert!(error.contains("model 'mock' not found"), "{}", error);
+    assert_eq!(envelope["response"], "");
+}
+
+#[tokio::test]
+async fn fails_over_to_the_next_server() {
+    let failing = MockServer::start().await;
+    failing.script("/api/generate", Reply::status(500, "{\"error\":\"out of memory\"}"));
+    let working = MockServer::start().await;
+    let mut config = test_config(failing.url());
+    config.url.push(working.url().to_string());
+    let output = Captured::default();
+    
+    oneshot_handler(context(config), &b"hi\n"[..], output.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(output.text(), "Answer: hi\n");
+    assert_eq!(failing.requests_to("/api/generate").len(), 1);
+    assert_eq!(working.requests_to("/api/generate").len(), 1);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_failed_over() {
+    let rejecting = MockServer::start().await;
+    rejecting.script("/api/generate", Reply::status(400, "{\"error\":\"invalid options\"}"));
+    let other = MockServer::start().await;
+    let mut config = test_config(rejecting.url());
+    config.url.push(other.url().to_string());
+    let ctx = context(config);
+    
+    oneshot_handler(Arc::clone(&ctx), &b"hi\n"[..], Captured::default().destination())
+        .await
+        .unwrap();
+    assert!(other.requests_to("/api/generate").is_empty());
+    
+    // Round robin comes back to the first server, which is still considered healthy
+    for input in [&b"two\n"[..], &b"three\n"[..]] {
+        oneshot_handler(Arc::clone(&ctx), input, Captured::default().destination())
+            .await
+            .unwrap();
+    }
+    assert_eq!(rejecting.requests_to("/api/generate").len(), 2);
+    assert_eq!(other.requests_to("/api/generate").len(), 1);
+}
+
+#[tokio::test]
+async fn writes_slow_answers_as_they_arrive() {
+    let server = MockServer::start().await;
+    let gate = Gate::new();
+    server.script("/api/generate", Reply::generate(&["one ", "two ", "three"]).gated(&gate));
+    let output = Captured::default();
+    let ctx = context(test_config(server.url()));
+    
+    let handler = tokio::spawn(oneshot_handler(ctx, &b"hi\n"[..], output.destination()));
+    // The rest of the answer is held back until the first piece has been written
+    let partial = output.wait_for(|text| !text.is_empty()).await;
+    assert_eq!(partial, "one ");
+    
+    gate.open();
+    handler.await.unwrap().unwrap();
+    assert_eq!(output.text(), "one two three\n");
+}
+
+#[tokio::test]
+async fn sends_numeric_keep_alive_as_a_number() {
+    let server = MockServer::start().await;
+    for (keep_alive, expected) in [("-1", json!(-1)), ("300", json!(300)), ("30m", json!("30m"))] {
+        let mut config = test_config(server.url());
+        config.keep_alive = Some(keep_alive.to_string());
+        oneshot_handler(context(config), &b"hi\n"[..], Captured::default().destination())
+            .await
+            .unwrap();
+        
+        let requests = server.requests_to("/api/generate");
+        assert_eq!(requests.last().unwrap().body["keep_alive"], expected);
+    }
+}
@@ -0,0 +1,69 @@
+mod support;
+
+use futures::StreamExt;
+use serde_json::json;
+
+use inlama::redact::{RedactRule, Redactor};
+use inlama::{Chunk, Inlama};
+use support::{done_line, generate_line, test_config, MockServer, Reply};
+
+fn redactor(rules: &[&str]) -> anyhow::Result<Redactor> {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.redact = true;
+    config.redact_rules = rules.iter().map(|rule| RedactRule::parse(rule)).collect::<anyhow::Result<_>>()?;
+    Ok(Redactor::from_config(&config)?.unwrap())
+}
+
+#[test]
+fn rule_names_must_make_restorable_placeholders() {
+    for name in ["my-rule", "order id", "", "ünïcode"] {
+        let error = redactor(&[&format!("{}=ORD-\\d+", name)]).err().unwrap();
+        assert!(error.to_string().contains("Invalid redact rule name"), "{}", error);
+    }
+    
+    let redactor = redactor(&["order_id=ORD-\\d+"]).unwrap();
+    assert_eq!(redactor.redact("see ORD-42"), "see <ORDER_ID_1>");
+    assert_eq!(redactor.original("<ORDER_ID_1>").as_deref(), Some("ORD-42"));
+}
+
+#[test]
+fn ipv6_addresses_are_redacted_but_hex_words_are_not() {
+    let redactor = redactor(&[]).unwrap();
+    
+    assert_eq!(redactor.redact("from fe80::1 and 2001:db8::8a2e:370:7334"), "from <IP_1> and <IP_2>");
+    for text in ["abc::def", "dead::beef", "use std::fmt", "a::b::c"] {
+        assert_eq!(redactor.redact(text), text);
+    }
+}
+
+#[tokio::test]
+async fn placeholders_in_thinking_are_restored() {
+    let server = MockServer::start().await;
+    server.script("/api/generate", Reply::lines(&[
+        generate_line(json!({ "thinking": "Mail from <EMA" })),
+        generate_line(json!({ "thinking": "IL_1>" })),
+        generate_line(json!({ "response": "Write to <EMAIL_1>" })),
+        done_line(&[1]),
+    ]));
+    let mut config = test_config(server.url());
+    config.redact = true;
+    config.unredact = true;
+    let inlama = Inlama::builder().config(config).build().unwrap();
+    
+    let chunks: Vec<Chunk> = inlama
+        .stream("from bob@example.com")
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    
+    let (mut thinking, mut text) = (String::new(), String::new());
+    for chunk in chunks {
+        match chunk {
+            Chunk::Thinking(part) => thinking.push_str(&part),
+            Chunk::Text(part) => text.push_str(&part),
+        }
+    }
+    assert_eq!(thinking, "Mail from bob@example.com");
+    assert_eq!(text, "Write to bob@example.com");
+    assert_eq!(server.requests_to("/api/generate")[0].body["prompt"], "from <EMAIL_1>");
+}
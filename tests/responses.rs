@@ -0,0 +1,103 @@
+mod support;
+
+use serde_json::json;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use inlama::endpoints::check_health;
+use inlama::requests::{process_stream_response, Chunk, Generation};
+use support::{done_line, generate_line, MockServer, Reply};
+
+/// Requests `/api/generate` from the mock and runs the response through the parser.
+async fn parse(reply: Reply) -> (Vec<Chunk>, Generation) {
+    let server = MockServer::start().await;
+    server.script("/api/generate", reply);
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/generate", server.url()))
+        .json(&json!({ "model": "mock", "prompt": "hi" }))
+        .send()
+        .await
+        .unwrap();
+    
+    let (tx, mut rx) = mpsc::channel(100);
+    let generation = process_stream_response(response.bytes_stream(), tx, Instant::now(), false).await.unwrap();
+    let mut chunks = Vec::new();
+    while let Some(chunk) = rx.recv().await {
+        chunks.push(chunk);
+    }
+    (chunks, generation)
+}
+
+fn text(chunks: &[Chunk]) -> String {
+    chunks
+        .iter()
+        .filter_map(|chunk| match chunk {
+            Chunk::Text(text) => Some(text.as_str()),
+            Chunk::Thinking(_) => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn parses_lines_split_mid_line_and_mid_character() {
+    for size in [1, 3, 5, 64] {
+        let (chunks, generation) = parse(Reply::generate(&["Grüße", ", ", "世界"]).split_every(size)).await;
+        assert_eq!(text(&chunks), "Grüße, 世界", "frames of {} bytes", size);
+        assert_eq!(generation.context, vec![1, 2, 3]);
+    }
+}
+
+#[tokio::test]
+async fn keeps_the_final_line_metadata() {
+    let (_, generation) = parse(Reply::generate(&["ok"])).await;
+    
+    assert_eq!(generation.model, "mock");
+    assert_eq!(generation.done_reason.as_deref(), Some("stop"));
+    assert_eq!(generation.stats.completion_tokens, Some(3));
+    assert_eq!(generation.stats.prompt_tokens, Some(10));
+    assert_eq!(generation.stats.total_ms, Some(5.0));
+}
+
+#[tokio::test]
+async fn skips_invalid_json() {
+    let (chunks, generation) = parse(Reply::lines(&[
+        "not json at all".to_string(),
+        generate_line(json!({ "response": "still " })),
+        "{\"response\": 42}".to_string(),
+        generate_line(json!({ "response": "here" })),
+        done_line(&[9]),
+    ]))
+    .await;
+    
+    assert_eq!(text(&chunks), "still here");
+    assert_eq!(generation.context, vec![9]);
+}
+
+#[tokio::test]
+async fn separates_thinking_from_the_answer() {
+    let (chunks, _) = parse(Reply::lines(&[
+        generate_line(json!({ "thinking": "Let me see." })),
+        generate_line(json!({ "response": "<think>inline</think>" })),
+        generate_line(json!({ "response": "42" })),
+        done_line(&[]),
+    ]))
+    .await;
+    
+    let thinking: Vec<&Chunk> = chunks.iter().filter(|c| matches!(c, Chunk::Thinking(_))).collect();
+    assert_eq!(
+        thinking,
+        vec![&Chunk::Thinking("Let me see.".to_string()), &Chunk::Thinking("inline".to_string())]
+    );
+    assert_eq!(text(&chunks), "42");
+}
+
+#[tokio::test]
+async fn health_check_falls_back_to_tags() {
+    let server = MockServer::start().await;
+    server.script("/api/version", Reply::status(404, "404 page not found"));
+    
+    assert!(check_health(&reqwest::Client::new(), server.url()).await);
+    
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, vec!["/api/version", "/api/tags"]);
+}
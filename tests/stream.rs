@@ -0,0 +1,303 @@
+mod support;
+
+use serde_json::json;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWriteExt, DuplexStream, ReadBuf};
+
+use inlama::handler::stream_handler;
+use inlama::signals::Cancellation;
+use support::{context, test_config, Captured, Gate, MockServer, Reply};
+
+fn stream_config(url: &str) -> inlama::Config {
+    let mut config = test_config(url);
+    config.stream = true;
+    config
+}
+
+#[tokio::test]
+async fn batches_continue_the_conversation() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.batch_max_lines = Some(2);
+    let output = Captured::default();
+    
+    stream_handler(context(config), &b"a\nb\nc\n"[..], output.destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(output.text(), "Answer: a\nb\nAnswer: c\n");
+    let requests = server.requests_to("/api/generate");
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].body["prompt"], "a\nb");
+    assert!(requests[0].body.get("context").is_none());
+    assert_eq!(requests[1].body["prompt"], "c");
+    assert_eq!(requests[1].body["context"], json!([1, 2, 3]));
+}
+
+#[tokio::test]
+async fn flushes_a_batch_while_input_stays_open() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.batch_max_latency = Some(0.1);
+    let output = Captured::default();
+    let (mut writer, reader) = tokio::io::duplex(1024);
+    
+    let handler = tokio::spawn(stream_handler(context(config), reader, output.destination()));
+    writer.write_all(b"first\n").await.unwrap();
+    // Answered while the input is still open
+    output.wait_for(|text| text == "Answer: first\n").await;
+    
+    writer.write_all(b"second\n").await.unwrap();
+    drop(writer);
+    handler.await.unwrap().unwrap();
+    
+    assert_eq!(output.text(), "Answer: first\nAnswer: second\n");
+    assert_eq!(server.requests_to("/api/generate").len(), 2);
+}
+
+#[tokio::test]
+async fn failed_batch_ends_the_stream_with_an_error() {
+    let server = MockServer::start().await;
+    server.script("/api/generate", Reply::status(500, "{\"error\":\"model crashed\"}"));
+    let output = Captured::default();
+    
+    let result = stream_handler(context(stream_config(server.url())), &b"a\n"[..], output.destination()).await;
+    
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("model crashed"), "{}", error);
+}
+
+#[tokio::test]
+async fn interrupted_batch_is_dropped_and_the_stream_continues() {
+    let server = MockServer::start().await;
+    let gate = Gate::new();
+    server.script("/api/generate", Reply::generate(&["never ", "finished ", "answer"]).gated(&gate));
+    let mut config = stream_config(server.url());
+    config.batch_max_lines = Some(1);
+    let ctx = context(config);
+    let output = Captured::default();
+    let (mut writer, reader) = tokio::io::duplex(1024);
+    
+    let handler = tokio::spawn(stream_handler(ctx.clone(), reader, output.destination()));
+    writer.write_all(b"slow\n").await.unwrap();
+    output.wait_for(|text| text == "never ").await;
+    assert!(!ctx.cancellation.interrupt(), "first interrupt should not exit");
+    gate.open();
+    
+    writer.write_all(b"next\n").await.unwrap();
+    drop(writer);
+    handler.await.unwrap().unwrap();
+    
+    assert_eq!(output.text(), "never \nAnswer: next\n");
+    let requests = server.requests_to("/api/generate");
+    assert_eq!(requests.len(), 2);
+    // The cancelled exchange is not part of the conversation
+    assert!(requests[1].body.get("context").is_none());
+}
+
+#[test]
+fn second_interrupt_exits_once_the_next_batch_has_started() {
+    let cancellation = Cancellation::new();
+    let first = cancellation.begin_generation();
+    assert!(!cancellation.interrupt());
+    drop(first);
+    
+    // The stream moves straight on to its next batch
+    let _next = cancellation.begin_generation();
+    assert!(cancellation.interrupt(), "a second interrupt should exit");
+}
+
+#[test]
+fn interrupts_are_forgotten_after_an_uninterrupted_batch() {
+    let cancellation = Cancellation::new();
+    drop(cancellation.begin_generation());
+    let cancelled = cancellation.begin_generation();
+    assert!(!cancellation.interrupt());
+    drop(cancelled);
+    
+    drop(cancellation.begin_generation());
+    let _next = cancellation.begin_generation();
+    assert!(!cancellation.interrupt(), "an interrupt after a completed batch should only cancel");
+}
+
+#[tokio::test]
+async fn pretty_json_output_is_rejected() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.output = inlama::output::OutputFormat::Json;
+    
+    let error = stream_handler(context(config), &b"a\n"[..], Captured::default().destination())
+        .await
+        .unwrap_err();
+    
+    assert!(error.to_string().contains("jsonl"), "{}", error);
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_batch_latency_is_an_error() {
+    let server = MockServer::start().await;
+    for latency in [-1.0, f64::NAN, f64::INFINITY] {
+        let mut config = stream_config(server.url());
+        config.batch_max_latency = Some(latency);
+        
+        let error = stream_handler(context(config), &b"a\n"[..], Captured::default().destination())
+            .await
+            .unwrap_err();
+        
+        assert!(error.to_string().contains("batch_max_latency"), "{}", error);
+    }
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn last_record_is_sent_once_input_goes_quiet() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.record_start = Some(r"^\d".to_string());
+    config.batch_max_latency = Some(0.2);
+    let output = Captured::default();
+    let (mut writer, reader) = tokio::io::duplex(1024);
+    
+    let handler = tokio::spawn(stream_handler(context(config), reader, output.destination()));
+    writer.write_all(b"1 first\n  at a\n2 second\n  at b\n").await.unwrap();
+    // No third start line arrives, but the second record is still answered
+    output.wait_for(|text| text.contains("2 second\n  at b")).await;
+    
+    drop(writer);
+    handler.await.unwrap().unwrap();
+    // Whether or not the two records shared a batch, neither was split
+    assert_eq!(prompts(&server).join("\n"), "1 first\n  at a\n2 second\n  at b");
+}
+
+/// Collects the prompts sent to the mock server, in order.
+fn prompts(server: &MockServer) -> Vec<String> {
+    server
+        .requests_to("/api/generate")
+        .iter()
+        .map(|r| r.body["prompt"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn multi_line_records_are_capped_at_max_line_bytes() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.record_start = Some(r"^\d".to_string());
+    config.max_line_bytes = 16;
+    config.batch_max_lines = Some(1);
+    let input = b"1 first\n  at a\n  at b\n  at c\n2 next\n";
+    
+    stream_handler(context(config), &input[..], Captured::default().destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(prompts(&server), ["1 first\n  at a\n ", "2 next"]);
+}
+
+#[tokio::test]
+async fn separated_records_are_cut_and_the_rest_skipped() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.record_sep = Some("--".to_string());
+    config.max_line_bytes = 8;
+    config.batch_max_lines = Some(1);
+    let input = b"abcdefghijkl\nmnop--\nshort--\n";
+    
+    stream_handler(context(config), &input[..], Captured::default().destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(prompts(&server), ["abcdefgh", "short"]);
+}
+
+#[tokio::test]
+async fn oversized_json_records_are_dropped() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.json_records = true;
+    config.max_line_bytes = 16;
+    config.batch_max_lines = Some(1);
+    let input = b"{\n\"a\": \"01234567\",\n\"b\": \"01234567\"\n}\n{\"c\": 2}\n";
+    
+    stream_handler(context(config), &input[..], Captured::default().destination())
+        .await
+        .unwrap();
+    
+    assert_eq!(prompts(&server), ["{\"c\": 2}"]);
+}
+
+#[tokio::test]
+async fn json_records_resync_after_an_unbalanced_line() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.json_records = true;
+    config.batch_max_lines = Some(1);
+    let input = b"oops {\n{\n  \"a\": 1\n}\n\"unterminated\n\n{\"b\": 2}\n";
+    
+    stream_handler(context(config), &input[..], Captured::default().destination())
+        .await
+        .unwrap();
+    
+    // The broken records are released as they are instead of absorbing the valid ones
+    assert_eq!(prompts(&server), ["oops {", "{\n  \"a\": 1\n}", "\"unterminated", "{\"b\": 2}"]);
+}
+
+/// Counts the bytes read through it, so a test knows when the handler has seen its input.
+struct CountingReader {
+    inner: DuplexStream,
+    read: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for CountingReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.read.fetch_add(buf.filled().len() - before, Ordering::SeqCst);
+        result
+    }
+}
+
+#[tokio::test]
+async fn shutdown_sends_the_record_being_assembled() {
+    let server = MockServer::start().await;
+    let mut config = stream_config(server.url());
+    config.record_start = Some(r"^\d".to_string());
+    // Long enough that only the shutdown can release the record
+    config.buffer_time = 30;
+    let ctx = context(config);
+    let output = Captured::default();
+    let (mut writer, inner) = tokio::io::duplex(1024);
+    let read = Arc::new(AtomicUsize::new(0));
+    let reader = CountingReader { inner, read: Arc::clone(&read) };
+    
+    let handler = tokio::spawn(stream_handler(ctx.clone(), reader, output.destination()));
+    let input = b"1 first\n  at a\n";
+    writer.write_all(input).await.unwrap();
+    while read.load(Ordering::SeqCst) < input.len() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    ctx.cancellation.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handler).await.unwrap().unwrap().unwrap();
+    
+    assert_eq!(output.text(), "Answer: 1 first\n  at a\n");
+    drop(writer);
+}
+
+#[tokio::test]
+async fn refused_binary_input_fails_the_stream() {
+    let server = MockServer::start().await;
+    let input: &[u8] = b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00>\x00\n";
+    
+    let error = stream_handler(context(stream_config(server.url())), input, Captured::default().destination())
+        .await
+        .unwrap_err();
+    
+    assert!(error.to_string().contains("binary"), "{}", error);
+    assert!(server.requests().is_empty());
+}
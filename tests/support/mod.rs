@@ -0,0 +1,395 @@
+//! A scripted stand-in for an Ollama server, plus helpers for driving the handlers.
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+use inlama::app::AppContext;
+use inlama::config::Config;
+use inlama::output::Destination;
+
+/// A request the mock server received.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub body: Value,
+}
+
+/// How the server answers one request.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    /// A chunked 200 response, each frame written and flushed on its own
+    Stream { frames: Vec<Vec<u8>>, delay: Duration, gate: Option<Gate> },
+    /// A plain response with the given status
+    Status { code: u16, body: String },
+}
+
+impl Reply {
+    /// A `/api/generate` stream answering with `pieces`, one line per frame.
+    pub fn generate(pieces: &[&str]) -> Self {
+        let mut lines: Vec<String> = pieces
+            .iter()
+            .map(|piece| generate_line(json!({ "response": piece, "done": false })))
+            .collect();
+        lines.push(done_line(&[1, 2, 3]));
+        Self::lines(&lines)
+    }
+    
+    /// A `/api/chat` stream answering with `pieces`.
+    pub fn chat(pieces: &[&str]) -> Self {
+        let mut lines: Vec<String> = pieces
+            .iter()
+            .map(|piece| chat_line(piece, false))
+            .collect();
+        lines.push(chat_line("", true));
+        Self::lines(&lines)
+    }
+    
+    /// A stream of raw NDJSON lines, one per frame.
+    pub fn lines(lines: &[String]) -> Self {
+        Self::Stream {
+            frames: lines.iter().map(|line| format!("{}\n", line).into_bytes()).collect(),
+            delay: Duration::from_millis(0),
+            gate: None,
+        }
+    }
+    
+    pub fn status(code: u16, body: &str) -> Self {
+        Self::Status { code, body: body.to_string() }
+    }
+    
+    /// Re-cuts the body into frames of `size` bytes, splitting lines (and characters) across frames.
+    pub fn split_every(self, size: usize) -> Self {
+        match self {
+            Self::Stream { frames, delay, gate } => Self::Stream {
+                frames: frames.concat().chunks(size).map(<[u8]>::to_vec).collect(),
+                // A short pause keeps the frames from being coalesced into one read
+                delay: delay.max(Duration::from_millis(2)),
+                gate,
+            },
+            other => other,
+        }
+    }
+    
+    /// Waits `delay` before every frame.
+    pub fn slow(self, delay: Duration) -> Self {
+        match self {
+            Self::Stream { frames, gate, .. } => Self::Stream { frames, delay, gate },
+            other => other,
+        }
+    }
+    
+    /// Sends the first frame, then holds the rest back until `gate` is opened.
+    pub fn gated(self, gate: &Gate) -> Self {
+        match self {
+            Self::Stream { frames, delay, .. } => Self::Stream { frames, delay, gate: Some(gate.clone()) },
+            other => other,
+        }
+    }
+}
+
+/// Lets a test decide when a [`Reply::gated`] stream carries on.
+#[derive(Debug, Clone)]
+pub struct Gate(Arc<Semaphore>);
+
+impl Gate {
+    pub fn new() -> Self {
+        Self(Arc::new(Semaphore::new(0)))
+    }
+    
+    /// Releases the held-back frames of every reply using this gate.
+    pub fn open(&self) {
+        // Waiting on a closed semaphore returns at once
+        self.0.close();
+    }
+    
+    async fn wait(&self) {
+        let _ = self.0.acquire().await;
+    }
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One streamed `/api/generate` line; `fields` are merged over the usual envelope.
+pub fn generate_line(fields: Value) -> String {
+    let mut line = json!({
+        "model": "mock",
+        "created_at": "2024-01-01T00:00:00Z",
+        "response": "",
+        "done": false,
+    });
+    if let (Some(line), Some(fields)) = (line.as_object_mut(), fields.as_object()) {
+        for (key, value) in fields {
+            line.insert(key.clone(), value.clone());
+        }
+    }
+    line.to_string()
+}
+
+/// The final `/api/generate` line, carrying the conversation context and stats.
+pub fn done_line(context: &[i32]) -> String {
+    generate_line(json!({
+        "done": true,
+        "done_reason": "stop",
+        "context": context,
+        "total_duration": 5_000_000,
+        "prompt_eval_count": 10,
+        "eval_count": 3,
+        "eval_duration": 2_000_000,
+    }))
+}
+
+fn chat_line(content: &str, done: bool) -> String {
+    json!({
+        "model": "mock",
+        "created_at": "2024-01-01T00:00:00Z",
+        "message": { "role": "assistant", "content": content },
+        "done": done,
+    })
+    .to_string()
+}
+
+#[derive(Default)]
+struct State {
+    scripts: HashMap<String, VecDeque<Reply>>,
+    requests: Vec<Recorded>,
+}
+
+/// A local HTTP server emulating `/api/generate`, `/api/chat`, `/api/tags` and `/api/version`.
+///
+/// Scripted replies are used in order for their path; once they run out,
+/// generate and chat echo the prompt back as `Answer: <prompt>`.
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+        let url = format!("http://{}", listener.local_addr().expect("mock server address"));
+        let state = Arc::new(Mutex::new(State::default()));
+        
+        let accept_state = Arc::clone(&state);
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let state = Arc::clone(&accept_state);
+                tokio::spawn(async move {
+                    let _ = serve(socket, state).await;
+                });
+            }
+        });
+        
+        Self { url, state, task }
+    }
+    
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    
+    /// Queues a reply for the next request to `path`.
+    pub fn script(&self, path: &str, reply: Reply) {
+        self.state
+            .lock()
+            .unwrap()
+            .scripts
+            .entry(path.to_string())
+            .or_default()
+            .push_back(reply);
+    }
+    
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.lock().unwrap().requests.clone()
+    }
+    
+    /// Requests to `path`, in the order they arrived.
+    pub fn requests_to(&self, path: &str) -> Vec<Recorded> {
+        self.requests().into_iter().filter(|r| r.path == path).collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(mut socket: TcpStream, state: Arc<Mutex<State>>) -> io::Result<()> {
+    // Read the head, then as much body as Content-Length announces
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let head_end = loop {
+        let read = socket.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        data.extend_from_slice(&buf[..read]);
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+    
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while data.len() < head_end + length {
+        let read = socket.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..read]);
+    }
+    let body: Value = serde_json::from_slice(&data[head_end..]).unwrap_or(Value::Null);
+    
+    let reply = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(Recorded {
+            method: method.clone(),
+            path: path.clone(),
+            body: body.clone(),
+        });
+        state.scripts.get_mut(&path).and_then(VecDeque::pop_front)
+    };
+    let reply = reply.unwrap_or_else(|| default_reply(&path, &body));
+    
+    match reply {
+        Reply::Status { code, body } => {
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                code,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await?;
+        }
+        Reply::Stream { frames, delay, gate } => {
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n")
+                .await?;
+            for (i, frame) in frames.into_iter().enumerate() {
+                if let Some(gate) = gate.as_ref().filter(|_| i > 0) {
+                    gate.wait().await;
+                }
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                socket.write_all(format!("{:x}\r\n", frame.len()).as_bytes()).await?;
+                socket.write_all(&frame).await?;
+                socket.write_all(b"\r\n").await?;
+                socket.flush().await?;
+            }
+            socket.write_all(b"0\r\n\r\n").await?;
+        }
+    }
+    socket.shutdown().await
+}
+
+fn default_reply(path: &str, body: &Value) -> Reply {
+    match path {
+        "/api/generate" => {
+            let prompt = body["prompt"].as_str().unwrap_or_default();
+            Reply::generate(&["Answer: ", prompt])
+        }
+        "/api/chat" => {
+            let prompt = body["messages"]
+                .as_array()
+                .and_then(|messages| messages.last())
+                .and_then(|message| message["content"].as_str())
+                .unwrap_or_default();
+            Reply::chat(&["Answer: ", prompt])
+        }
+        "/api/tags" => Reply::Stream {
+            frames: vec![json!({ "models": [{ "name": "mock", "model": "mock" }] }).to_string().into_bytes()],
+            delay: Duration::from_millis(0),
+            gate: None,
+        },
+        "/api/version" => Reply::Stream {
+            frames: vec![json!({ "version": "0.0.0-mock" }).to_string().into_bytes()],
+            delay: Duration::from_millis(0),
+            gate: None,
+        },
+        _ => Reply::status(404, "{\"error\":\"not found\"}"),
+    }
+}
+
+/// Defaults suitable for tests: the mock server, and nothing read from the environment.
+pub fn test_config(url: &str) -> Config {
+    Config {
+        url: vec![url.to_string()],
+        model: "mock".to_string(),
+        prompt: "Test prompt".to_string(),
+        // Tests must never read answers cached by an earlier run
+        cache: false,
+        ..Config::default()
+    }
+}
+
+pub fn context(config: Config) -> Arc<AppContext> {
+    Arc::new(AppContext::new(config).expect("build app context"))
+}
+
+/// A cloneable in-memory writer for capturing handler output.
+#[derive(Clone, Default)]
+pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    pub fn destination(&self) -> Destination {
+        Destination::Writer(Box::new(self.clone()))
+    }
+    
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).expect("output is UTF-8")
+    }
+    
+    /// Polls until the output satisfies `done`, failing the test after five seconds.
+    pub async fn wait_for(&self, done: impl Fn(&str) -> bool) -> String {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let text = self.text();
+            if done(&text) {
+                return text;
+            }
+            assert!(tokio::time::Instant::now() < deadline, "timed out waiting for output, got {:?}", text);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An empty directory under the system temp dir, unique to this test process.
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("inlama-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
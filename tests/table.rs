@@ -0,0 +1,45 @@
+mod support;
+
+use csv::StringRecord;
+use std::io::Cursor;
+
+use inlama::handler::table_handler;
+use inlama::table::{RowTemplate, TableFormat};
+use support::{context, test_config, Captured, MockServer};
+
+#[test]
+fn placeholders_can_name_headers_with_spaces() {
+    let headers = StringRecord::from(vec!["First Name", " Last Name ", "id"]);
+    let template = RowTemplate::new(Some("{{row.First Name}} {{ row.Last Name }} ({{row.id}})"), headers).unwrap();
+    
+    let rendered = template.render(&StringRecord::from(vec!["Ada", "Lovelace", "7"]));
+    
+    assert_eq!(rendered, "Ada Lovelace (7)");
+}
+
+#[test]
+fn unknown_columns_are_reported() {
+    let headers = StringRecord::from(vec!["First Name", "id"]);
+    
+    let error = RowTemplate::new(Some("{{row.First}} {{row.Surname}}"), headers).err().unwrap();
+    
+    let message = error.to_string();
+    assert!(message.contains("unknown columns: First, Surname"), "{}", message);
+}
+
+#[tokio::test]
+async fn rows_are_answered_with_the_template() {
+    let server = MockServer::start().await;
+    let mut config = test_config(server.url());
+    config.table = Some(TableFormat::Csv);
+    config.row_template = Some("Ticket: {{row.Ticket Title}}".to_string());
+    let output = Captured::default();
+    
+    let input = Cursor::new(b"Ticket Title,Owner\nDisk full,ops\nLogin broken,web\n".to_vec());
+    table_handler(context(config), input, output.destination()).await.unwrap();
+    
+    assert_eq!(
+        output.text(),
+        "Ticket Title,Owner,answer\nDisk full,ops,Answer: Ticket: Disk full\nLogin broken,web,Answer: Ticket: Login broken\n"
+    );
+}